@@ -5,31 +5,33 @@ use winit::{
     window::WindowBuilder,
 };
 
-use crate::renderer::RendererState;
-
-pub async fn init() {
-    // more beautiful logging
-    env_logger::init();
+use crate::{
+    cli::Options,
+    renderer::{RendererError, RendererState},
+    RunError,
+};
 
-    let event_loop = EventLoopBuilder::new().build().unwrap();
+pub async fn init(options: &Options) -> Result<(), RunError> {
+    let event_loop = EventLoopBuilder::new().build()?;
 
     let window = WindowBuilder::new()
         .with_title("snagg")
-        .build(&event_loop)
-        .unwrap();
+        .build(&event_loop)?;
 
-    let mut renderer = RendererState::new(&window).await;
+    let mut renderer =
+        RendererState::new(&window, options.scene.as_deref(), options.settings()).await?;
+    let window = &window;
 
-    let render_loop_result = event_loop.run(move |event, target| match event {
+    event_loop.run(move |event, target| match event {
         // Close window, uppon requesting
-        Event::WindowEvent { window_id, event } if window_id == renderer.config.window.id() => {
+        Event::WindowEvent { window_id, event } if window_id == window.id() => {
             match event {
                 WindowEvent::CloseRequested => target.exit(),
                 WindowEvent::Resized(physical_size) => {
                     renderer.resize(physical_size);
                 }
                 WindowEvent::RedrawRequested => {
-                    renderer.window().request_redraw();
+                    window.request_redraw();
 
                     // TODO: Render Update should happen here!
 
@@ -56,7 +58,17 @@ pub async fn init() {
             }
         }
         _ => (),
-    });
+    })?;
+
+    Ok(())
+}
+
+/// Renders a single frame without a window and writes it to `options.output`
+pub async fn capture(options: &Options) -> Result<(), RendererError> {
+    let mut renderer =
+        RendererState::headless(options.size, options.scene.as_deref(), options.settings()).await?;
+    renderer.save_png(&options.output)?;
 
-    info!("Render loop result: {:?}", render_loop_result);
+    info!("Wrote frame to {}", options.output.display());
+    Ok(())
 }
@@ -1,3 +1,9 @@
+use std::{
+    collections::HashMap,
+    num::NonZeroU32,
+    sync::{Arc, Mutex},
+};
+
 use crate::renderer::Configuration;
 
 pub struct BindGroupEntryInfo<'a> {
@@ -5,20 +11,217 @@ pub struct BindGroupEntryInfo<'a> {
     pub visibility: wgpu::ShaderStages,
     /// what data the bindgroup will be using
     pub resource: wgpu::BindingResource<'a>,
+    /// how the shader sees the resource, inferred from `resource` unless set explicitly
+    pub ty: wgpu::BindingType,
 }
 
 pub struct BindGroupInfo {
-    pub layout: wgpu::BindGroupLayout,
+    /// Shared by all bind groups with the same entries, see [`LayoutCache`]
+    pub layout: Arc<wgpu::BindGroupLayout>,
     pub group: wgpu::BindGroup,
 }
 
+/// A resource a [`BindGroupEntryInfo`] can bind, with the binding type it is inferred to have.
+/// <br>
+/// Buffers are bound as uniforms without dynamic offset, texture views as filterable 2D float
+/// textures and samplers as filtering samplers, arrays of them the same with a count.
+pub trait EntryResource<'a> {
+    fn into_binding(self) -> (wgpu::BindingResource<'a>, wgpu::BindingType);
+}
+
+const UNIFORM: wgpu::BindingType = wgpu::BindingType::Buffer {
+    ty: wgpu::BufferBindingType::Uniform,
+    has_dynamic_offset: false,
+    min_binding_size: None,
+};
+
+const TEXTURE: wgpu::BindingType = wgpu::BindingType::Texture {
+    sample_type: wgpu::TextureSampleType::Float { filterable: true },
+    view_dimension: wgpu::TextureViewDimension::D2,
+    multisampled: false,
+};
+
+const SAMPLER: wgpu::BindingType = wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering);
+
+impl<'a> EntryResource<'a> for wgpu::BufferBinding<'a> {
+    fn into_binding(self) -> (wgpu::BindingResource<'a>, wgpu::BindingType) {
+        (wgpu::BindingResource::Buffer(self), UNIFORM)
+    }
+}
+
+impl<'a> EntryResource<'a> for &'a [wgpu::BufferBinding<'a>] {
+    fn into_binding(self) -> (wgpu::BindingResource<'a>, wgpu::BindingType) {
+        (wgpu::BindingResource::BufferArray(self), UNIFORM)
+    }
+}
+
+impl<'a> EntryResource<'a> for &'a wgpu::TextureView {
+    fn into_binding(self) -> (wgpu::BindingResource<'a>, wgpu::BindingType) {
+        (wgpu::BindingResource::TextureView(self), TEXTURE)
+    }
+}
+
+impl<'a> EntryResource<'a> for &'a [&'a wgpu::TextureView] {
+    fn into_binding(self) -> (wgpu::BindingResource<'a>, wgpu::BindingType) {
+        (wgpu::BindingResource::TextureViewArray(self), TEXTURE)
+    }
+}
+
+impl<'a> EntryResource<'a> for &'a wgpu::Sampler {
+    fn into_binding(self) -> (wgpu::BindingResource<'a>, wgpu::BindingType) {
+        (wgpu::BindingResource::Sampler(self), SAMPLER)
+    }
+}
+
+impl<'a> EntryResource<'a> for &'a [&'a wgpu::Sampler] {
+    fn into_binding(self) -> (wgpu::BindingResource<'a>, wgpu::BindingType) {
+        (wgpu::BindingResource::SamplerArray(self), SAMPLER)
+    }
+}
+
 impl<'a> BindGroupEntryInfo<'a> {
-    pub fn new(visibility: wgpu::ShaderStages, resource: wgpu::BindingResource<'a>) -> Self {
+    /// An entry with the binding type inferred from the resource, see [`EntryResource`].
+    /// <br>
+    /// Anything else, like storage buffers, cube maps or depth textures, is declared with
+    /// [`BindGroupEntryInfo::with_type`].
+    pub fn new(visibility: wgpu::ShaderStages, resource: impl EntryResource<'a>) -> Self {
+        let (resource, ty) = resource.into_binding();
+
+        Self::typed(visibility, resource, ty)
+    }
+
+    /// A uniform buffer offset when setting the bind group, of which the shader sees `size` bytes
+    pub fn dynamic(
+        visibility: wgpu::ShaderStages,
+        buffer: &'a wgpu::Buffer,
+        size: wgpu::BufferSize,
+    ) -> Self {
+        Self::typed(
+            visibility,
+            wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+                buffer,
+                offset: 0,
+                size: Some(size),
+            }),
+            wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Uniform,
+                has_dynamic_offset: true,
+                min_binding_size: Some(size),
+            },
+        )
+    }
+
+    /// An entry with an explicit binding type, which works for every resource wgpu has
+    pub fn typed(
+        visibility: wgpu::ShaderStages,
+        resource: wgpu::BindingResource<'a>,
+        ty: wgpu::BindingType,
+    ) -> Self {
         Self {
             visibility,
             resource,
+            ty,
         }
     }
+
+    /// Overrides the inferred binding type
+    pub fn with_type(mut self, ty: wgpu::BindingType) -> Self {
+        self.ty = ty;
+        self
+    }
+
+    /// Binds a read-only or read-write storage buffer
+    pub fn storage(self, read_only: bool) -> Self {
+        self.with_type(wgpu::BindingType::Buffer {
+            ty: wgpu::BufferBindingType::Storage { read_only },
+            has_dynamic_offset: false,
+            min_binding_size: None,
+        })
+    }
+
+    /// Binds a texture of another dimension or sample type than a filterable 2D float texture
+    pub fn texture(
+        self,
+        view_dimension: wgpu::TextureViewDimension,
+        sample_type: wgpu::TextureSampleType,
+    ) -> Self {
+        self.with_type(wgpu::BindingType::Texture {
+            sample_type,
+            view_dimension,
+            multisampled: false,
+        })
+    }
+
+    /// Binds a texture view as a storage texture
+    pub fn storage_texture(
+        self,
+        access: wgpu::StorageTextureAccess,
+        format: wgpu::TextureFormat,
+        view_dimension: wgpu::TextureViewDimension,
+    ) -> Self {
+        self.with_type(wgpu::BindingType::StorageTexture {
+            access,
+            format,
+            view_dimension,
+        })
+    }
+
+    /// Binds a sampler of another kind than a filtering one, like comparison samplers of shadows
+    pub fn sampler(self, kind: wgpu::SamplerBindingType) -> Self {
+        self.with_type(wgpu::BindingType::Sampler(kind))
+    }
+
+    /// The number of elements of binding arrays
+    fn count(&self) -> Option<NonZeroU32> {
+        let count = match &self.resource {
+            wgpu::BindingResource::BufferArray(buffers) => buffers.len(),
+            wgpu::BindingResource::TextureViewArray(views) => views.len(),
+            wgpu::BindingResource::SamplerArray(samplers) => samplers.len(),
+            _ => return None,
+        };
+
+        NonZeroU32::new(count as u32)
+    }
+
+    fn layout_entry(&self, binding: u32) -> wgpu::BindGroupLayoutEntry {
+        wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: self.visibility,
+            ty: self.ty,
+            count: self.count(),
+        }
+    }
+}
+
+/// Bind group layouts by their entries, so that bind groups with the same entries share one
+/// layout object
+#[derive(Default)]
+pub struct LayoutCache {
+    layouts: Mutex<HashMap<Vec<wgpu::BindGroupLayoutEntry>, Arc<wgpu::BindGroupLayout>>>,
+}
+
+impl LayoutCache {
+    /// The layout with `entries`, created if it doesn't exist yet
+    pub fn get(
+        &self,
+        device: &wgpu::Device,
+        entries: &[wgpu::BindGroupLayoutEntry],
+        label: &str,
+    ) -> Arc<wgpu::BindGroupLayout> {
+        let mut layouts = self.layouts.lock().expect("layout cache is poisoned");
+
+        layouts
+            .entry(entries.to_vec())
+            .or_insert_with(|| {
+                Arc::new(
+                    device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                        entries,
+                        label: Some(&format!("Bindgroup layout for '{}'", label)),
+                    }),
+                )
+            })
+            .clone()
+    }
 }
 
 pub fn create_bindgroup(
@@ -26,25 +229,15 @@ pub fn create_bindgroup(
     entries: &[BindGroupEntryInfo],
     label: &str,
 ) -> BindGroupInfo {
+    let layout_entries: Vec<_> = entries
+        .iter()
+        .enumerate()
+        .map(|(i, entry)| entry.layout_entry(i as u32))
+        .collect();
+
     let layout = config
-        .device
-        .create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-            entries: &entries
-                .iter()
-                .enumerate()
-                .map(|(i, entry)| wgpu::BindGroupLayoutEntry {
-                    binding: i as u32,
-                    visibility: entry.visibility,
-                    ty: wgpu::BindingType::Buffer {
-                        ty: wgpu::BufferBindingType::Uniform,
-                        has_dynamic_offset: false,
-                        min_binding_size: None,
-                    },
-                    count: None,
-                })
-                .collect::<Vec<_>>(),
-            label: Some(&format!("Bindgroup layout for '{}'", label)),
-        });
+        .layout_cache
+        .get(&config.device, &layout_entries, label);
 
     let bind_group = config.device.create_bind_group(&wgpu::BindGroupDescriptor {
         layout: &layout,
@@ -1,82 +1,180 @@
-use getset::Getters;
-use gltf::buffer;
+use std::sync::Arc;
+
+use getset::{CopyGetters, Getters};
 
 use crate::{
     bindgroup::{create_bindgroup, BindGroupEntryInfo, BindGroupInfo},
-    math::{mat::Mat4, transform::Transform, vec::Vec4},
+    depth::DepthConfig,
+    math::{mat::Mat4, transform::Transform, vec::Vec3},
     renderer::Configuration,
     uniform_buffer::create_uniform_buffer,
 };
 
+/// How the camera maps the view space onto the screen
+#[derive(Clone, Copy, Debug)]
+pub enum Projection {
+    Perspective {
+        /// Field of view on the Y-Axis
+        fov: f32,
+    },
+    /// `xmag` is ignored in favour of the aspect ratio of the window, so the image doesn't stretch
+    Orthographic {
+        /// Half the width of the view volume
+        xmag: f32,
+        /// Half the height of the view volume
+        ymag: f32,
+    },
+}
+
 #[derive(Clone, Debug)]
 pub struct CameraData {
     pub near: f32,
     pub far: f32,
-    /// Field of view on the Y-Axis
-    pub fov: f32,
+    pub projection: Projection,
 
     pub transform: Transform,
 }
 
 impl CameraData {
-    pub fn new(near: f32, far: f32, fov: f32, transform: Transform) -> Self {
+    pub fn new(near: f32, far: f32, projection: Projection, transform: Transform) -> Self {
         Self {
             near,
             far,
-            fov,
+            projection,
             transform,
         }
     }
+
+    /// The inverse of the world transform, the scale of the camera is ignored
+    pub fn view(&self) -> Mat4 {
+        let Transform {
+            position, rotation, ..
+        } = &self.transform;
+
+        // inverting a rotation and translation only, without the general matrix inverse
+        let rotation = rotation.conjugate();
+        Mat4::from_translation(-(rotation * *position)) * rotation.to_mat4()
+    }
+
+    pub fn projection(&self, aspect: f32) -> Mat4 {
+        match self.projection {
+            Projection::Perspective { fov } => Mat4::perspective(fov, aspect, self.near, self.far),
+            Projection::Orthographic { ymag, .. } => {
+                Mat4::orthographic(ymag * aspect, ymag, self.near, self.far)
+            }
+        }
+    }
 }
 
-#[derive(Getters)]
+/// The camera as seen by the shaders, matching `CameraUniform` in the WGSL code
+#[derive(Clone, Copy, Debug)]
+pub struct CameraUniform {
+    pub view: Mat4,
+    pub projection: Mat4,
+    pub view_projection: Mat4,
+    /// World space position of the camera
+    pub position: Vec3,
+}
+
+impl CameraUniform {
+    pub fn new(data: &CameraData, aspect: f32, depth: &DepthConfig) -> Self {
+        let view = data.view();
+        let projection = depth.projection_correction() * data.projection(aspect);
+
+        Self {
+            view,
+            projection,
+            view_projection: projection * view,
+            position: data.transform.position,
+        }
+    }
+
+    pub fn bytes(self) -> Vec<u8> {
+        let mut bytes = self.view.bytes();
+        bytes.extend(self.projection.bytes());
+        bytes.extend(self.view_projection.bytes());
+        // a vec3 is padded to 16 bytes in uniform buffers
+        bytes.extend(self.position.extend(1.0).bytes());
+        bytes
+    }
+}
+
+#[derive(Getters, CopyGetters)]
 pub struct Camera {
+    #[getset(get = "pub")]
     data: CameraData,
+    /// Width divided by height of the render target
+    #[getset(get_copy = "pub")]
+    aspect: f32,
+    depth: DepthConfig,
+    #[getset(get = "pub")]
     buffer: wgpu::Buffer,
     #[getset(get = "pub")]
     bind_group: BindGroupInfo,
 }
 
 impl Camera {
-    pub fn new(config: &Configuration, data: CameraData, label: &str) -> Self {
-        let CameraData { near, far, fov, .. } = data;
+    /// Where the uniform is visible, shared by the bind group of every camera and [`Camera::layout`]
+    const VISIBILITY: wgpu::ShaderStages = wgpu::ShaderStages::VERTEX_FRAGMENT;
 
-        let y = (fov / 2.0).tan();
-        let aspect = config.size.width as f32 / config.size.height as f32;
+    /// The layout of the bind group of every camera, for pipelines created before the camera
+    pub fn layout(config: &Configuration) -> Arc<wgpu::BindGroupLayout> {
+        config.layout_cache.get(
+            &config.device,
+            &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: Self::VISIBILITY,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            }],
+            "Camera",
+        )
+    }
 
-        let projection = Mat4::new(
-            Vec4::new(1.0 / (y * aspect), 0.0, 0.0, 0.0),
-            Vec4::new(0.0, 1.0 / y, 0.0, 0.0),
-            Vec4::new(0.0, 0.0, far / (far - near), 1.0),
-            Vec4::new(0.0, 0.0, (-far * near) / (far - near), 0.0),
-        );
+    pub fn new(config: &Configuration, data: CameraData, label: &str) -> Self {
+        let aspect = config.size.width as f32 / config.size.height as f32;
 
-        // let projection = Mat4::new(
-        //     Vec4::new(near, 0.0, 0.0, 0.0),
-        //     Vec4::new(0.0, near, 0.0, 0.0),
-        //     Vec4::new(0.0, 0.0, far / (far - near), 1.0),
-        //     Vec4::new(0.0, 0.0, (-far * near) / (far - near), 0.0),
-        // );
+        let depth = config.settings.depth;
+        let uniform = CameraUniform::new(&data, aspect, &depth);
 
-        let buffer = create_uniform_buffer(config, label, &projection.bytes());
+        let buffer = create_uniform_buffer(config, label, &uniform.bytes());
 
         let bind_group = create_bindgroup(
             config,
             &[BindGroupEntryInfo::new(
-                wgpu::ShaderStages::VERTEX,
-                wgpu::BindingResource::Buffer(wgpu::BufferBinding {
-                    buffer: &buffer,
-                    offset: 0,
-                    size: None,
-                }),
+                Self::VISIBILITY,
+                buffer.as_entire_buffer_binding(),
             )],
             label,
         );
 
         Self {
             data,
+            aspect,
+            depth,
             buffer,
             bind_group,
         }
     }
+
+    /// Replaces the camera data and rewrites the uniform buffer, can be called every frame
+    pub fn update(&mut self, queue: &wgpu::Queue, data: &CameraData) {
+        self.data = data.clone();
+        self.write(queue);
+    }
+
+    /// Recomputes the projection for a render target of a new size
+    pub fn resize(&mut self, queue: &wgpu::Queue, size: winit::dpi::PhysicalSize<u32>) {
+        self.aspect = size.width as f32 / size.height as f32;
+        self.write(queue);
+    }
+
+    fn write(&self, queue: &wgpu::Queue) {
+        let uniform = CameraUniform::new(&self.data, self.aspect, &self.depth);
+        queue.write_buffer(&self.buffer, 0, &uniform.bytes());
+    }
 }
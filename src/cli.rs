@@ -0,0 +1,131 @@
+use std::path::PathBuf;
+
+use winit::dpi::PhysicalSize;
+
+use crate::{
+    environment::EnvironmentConfig,
+    renderer::RenderSettings,
+    shadow::ShadowConfig,
+    tonemap::{TonemapConfig, Tonemapper},
+};
+
+pub const USAGE: &str = "usage: render [scene.gltf] [--headless] [--output frame.png] \
+    [--size WIDTHxHEIGHT] [--environment sky.hdr] [--tonemap aces|agx|reinhard|neutral] \
+    [--exposure EV] [--auto-exposure] [--debug-cascades] [--fallback-light]";
+
+/// The command line of the binary
+#[derive(Clone, Debug)]
+pub struct Options {
+    /// The glTF / GLB file to render, the bundled scene if `None`
+    pub scene: Option<PathBuf>,
+    /// Render a single frame to `output` instead of opening a window
+    pub headless: bool,
+    pub output: PathBuf,
+    /// Size of headless frames
+    pub size: PhysicalSize<u32>,
+    /// Equirectangular environment map lighting the scene and drawn behind it
+    pub environment: Option<PathBuf>,
+    pub tonemapper: Tonemapper,
+    /// Exposure in EV, or the compensation of the automatic exposure
+    pub exposure: f32,
+    pub auto_exposure: bool,
+    /// Tint the scene by shadow cascade
+    pub debug_cascades: bool,
+    /// Add a directional light to scenes without lights
+    pub fallback_light: bool,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            scene: None,
+            headless: false,
+            output: PathBuf::from("frame.png"),
+            size: PhysicalSize::new(800, 600),
+            environment: None,
+            tonemapper: Tonemapper::default(),
+            exposure: 0.0,
+            auto_exposure: false,
+            debug_cascades: false,
+            fallback_light: false,
+        }
+    }
+}
+
+impl Options {
+    pub fn parse(args: impl IntoIterator<Item = String>) -> Result<Self, String> {
+        let mut options = Self::default();
+        let mut args = args.into_iter();
+        // flags only headless rendering uses, rejected without `--headless`
+        let mut headless_flag = None;
+
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--headless" => options.headless = true,
+                "--debug-cascades" => options.debug_cascades = true,
+                "--auto-exposure" => options.auto_exposure = true,
+                "--fallback-light" => options.fallback_light = true,
+                "--tonemap" => {
+                    options.tonemapper = args.next().ok_or("--tonemap needs a name")?.parse()?;
+                }
+                "--exposure" => {
+                    let exposure = args.next().ok_or("--exposure needs a value")?;
+                    options.exposure = exposure
+                        .parse()
+                        .map_err(|_| format!("invalid exposure '{exposure}', expected EV"))?;
+                }
+                "--output" => {
+                    headless_flag = Some("--output");
+                    options.output = args.next().ok_or("--output needs a path")?.into();
+                }
+                "--environment" => {
+                    let path = args.next().ok_or("--environment needs a path")?;
+                    options.environment = Some(path.into());
+                }
+                "--size" => {
+                    headless_flag = Some("--size");
+                    let size = args.next().ok_or("--size needs a size")?;
+                    options.size = parse_size(&size)
+                        .ok_or_else(|| format!("invalid size '{size}', expected e.g. 800x600"))?;
+                }
+                flag if flag.starts_with("--") => return Err(format!("unknown flag '{flag}'")),
+                _ if options.scene.is_some() => return Err("only one scene can be rendered".into()),
+                _ => options.scene = Some(arg.into()),
+            }
+        }
+
+        if let (Some(flag), false) = (headless_flag, options.headless) {
+            return Err(format!("{flag} only applies with --headless"));
+        }
+
+        Ok(options)
+    }
+
+    pub fn settings(&self) -> RenderSettings {
+        RenderSettings {
+            shadows: ShadowConfig {
+                debug_cascades: self.debug_cascades,
+                ..Default::default()
+            },
+            environment: EnvironmentConfig {
+                path: self.environment.clone(),
+                ..Default::default()
+            },
+            tonemap: TonemapConfig {
+                tonemapper: self.tonemapper,
+                exposure: self.exposure,
+                auto_exposure: self.auto_exposure,
+                ..Default::default()
+            },
+            fallback_light: self.fallback_light,
+            ..Default::default()
+        }
+    }
+}
+
+fn parse_size(size: &str) -> Option<PhysicalSize<u32>> {
+    let (width, height) = size.split_once('x')?;
+    let size = PhysicalSize::new(width.parse().ok()?, height.parse().ok()?);
+
+    (size.width > 0 && size.height > 0).then_some(size)
+}
@@ -0,0 +1,121 @@
+use getset::Getters;
+
+use crate::{
+    math::{mat::Mat4, vec::Vec4},
+    renderer::Configuration,
+};
+
+/// How depth is stored and compared in the main pass
+#[derive(Clone, Copy, Debug)]
+pub struct DepthConfig {
+    /// Either `Depth32Float` or `Depth24PlusStencil8`
+    pub format: wgpu::TextureFormat,
+    /// Maps the near plane to 1 and the far plane to 0, which distributes float precision a lot
+    /// more evenly over the depth range
+    pub reverse_z: bool,
+}
+
+impl Default for DepthConfig {
+    fn default() -> Self {
+        Self {
+            format: wgpu::TextureFormat::Depth32Float,
+            reverse_z: false,
+        }
+    }
+}
+
+impl DepthConfig {
+    /// The comparison that lets closer fragments pass
+    pub fn compare(&self) -> wgpu::CompareFunction {
+        if self.reverse_z {
+            wgpu::CompareFunction::Greater
+        } else {
+            wgpu::CompareFunction::Less
+        }
+    }
+
+    /// The depth of the far plane
+    pub fn clear_value(&self) -> f32 {
+        if self.reverse_z {
+            0.0
+        } else {
+            1.0
+        }
+    }
+
+    /// Applied after the projection to flip the depth range if reverse-Z is enabled
+    pub fn projection_correction(&self) -> Mat4 {
+        if self.reverse_z {
+            // z' = w - z
+            Mat4::new(
+                Vec4::new(1.0, 0.0, 0.0, 0.0),
+                Vec4::new(0.0, 1.0, 0.0, 0.0),
+                Vec4::new(0.0, 0.0, -1.0, 0.0),
+                Vec4::new(0.0, 0.0, 1.0, 1.0),
+            )
+        } else {
+            Mat4::identity()
+        }
+    }
+
+    pub fn depth_stencil_state(&self) -> wgpu::DepthStencilState {
+        wgpu::DepthStencilState {
+            format: self.format,
+            depth_write_enabled: true,
+            depth_compare: self.compare(),
+            stencil: Default::default(),
+            bias: Default::default(),
+        }
+    }
+}
+
+/// The depth attachment of the main pass, has to be recreated whenever the surface size changes
+#[derive(Getters)]
+pub struct DepthTexture {
+    #[getset(get = "pub")]
+    texture: wgpu::Texture,
+    #[getset(get = "pub")]
+    view: wgpu::TextureView,
+}
+
+impl DepthTexture {
+    pub fn new(config: &Configuration) -> Self {
+        let texture = config.device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Depth texture"),
+            size: wgpu::Extent3d {
+                width: config.size.width.max(1),
+                height: config.size.height.max(1),
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: config.settings.depth.format,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+            view_formats: &[],
+        });
+
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+        Self { texture, view }
+    }
+
+    pub fn attachment<'a>(
+        &'a self,
+        config: &Configuration,
+    ) -> wgpu::RenderPassDepthStencilAttachment<'a> {
+        let has_stencil = config.settings.depth.format.has_stencil_aspect();
+
+        wgpu::RenderPassDepthStencilAttachment {
+            view: &self.view,
+            depth_ops: Some(wgpu::Operations {
+                load: wgpu::LoadOp::Clear(config.settings.depth.clear_value()),
+                store: wgpu::StoreOp::Store,
+            }),
+            stencil_ops: has_stencil.then_some(wgpu::Operations {
+                load: wgpu::LoadOp::Clear(0),
+                store: wgpu::StoreOp::Store,
+            }),
+        }
+    }
+}
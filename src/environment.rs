@@ -0,0 +1,514 @@
+use std::{path::PathBuf, sync::Arc};
+
+use getset::{CopyGetters, Getters};
+use image::{imageops::FilterType, Rgba32FImage};
+use log::info;
+
+use crate::{
+    bindgroup::{create_bindgroup, BindGroupEntryInfo, BindGroupInfo},
+    camera::Camera,
+    math::vec::Vec4,
+    pipeline::{self, PipelineDescriptor},
+    renderer::Configuration,
+    texture::MipGenerator,
+    uniform_buffer::create_uniform_buffer,
+};
+
+/// Mips of the specular cube, from mirror like at the first to fully rough at the last
+const SPECULAR_MIPS: u32 = 6;
+
+/// Face size of the irradiance cube, diffuse lighting has hardly any detail
+const IRRADIANCE_RESOLUTION: u32 = 32;
+
+const BRDF_LUT_RESOLUTION: u32 = 128;
+
+/// Samples per texel of the specular cube, few suffice as every sample reads a mip matching its
+/// footprint
+const SPECULAR_SAMPLES: u32 = 64;
+
+const IRRADIANCE_SAMPLES: u32 = 256;
+
+const BRDF_LUT_SAMPLES: u32 = 512;
+
+/// Where image based lighting and the background come from
+#[derive(Clone, Debug)]
+pub struct EnvironmentConfig {
+    /// An equirectangular Radiance `.hdr` image, or OpenEXR with the `exr` feature.
+    /// <br>
+    /// Without one, materials get a constant ambient term and the background is cleared to
+    /// `background`.
+    pub path: Option<PathBuf>,
+    /// Scales the image based lighting and the skybox, to match the image with the lights
+    pub intensity: f32,
+    /// Face size of the cube the image is converted to, which the skybox is drawn from
+    pub cube_resolution: u32,
+    /// Face size of the first mip of the prefiltered specular cube
+    pub specular_resolution: u32,
+    /// Clear color of the color pass, hidden by the skybox if there is an environment
+    pub background: wgpu::Color,
+}
+
+impl Default for EnvironmentConfig {
+    fn default() -> Self {
+        Self {
+            path: None,
+            intensity: 1.0,
+            cube_resolution: 512,
+            specular_resolution: 128,
+            background: wgpu::Color {
+                r: 0.8,
+                g: 0.2,
+                b: 0.3,
+                a: 1.0,
+            },
+        }
+    }
+}
+
+/// An environment map prepared for image based lighting.
+/// <br>
+/// The image is converted to a cube on the GPU, from which a specular cube with one roughness
+/// per mip and a diffuse irradiance cube are prefiltered. Together with the BRDF lookup table
+/// they are sampled by the material shader through the light group, see [`Environment::entries`].
+/// <br>
+/// Without an image, the textures are black placeholders and the shader falls back to a constant
+/// ambient term.
+#[derive(Getters, CopyGetters)]
+pub struct Environment {
+    /// Whether an image was loaded
+    #[getset(get_copy = "pub")]
+    loaded: bool,
+    /// The image as a cube with a full mip chain, for the skybox
+    #[getset(get = "pub")]
+    cube: wgpu::Texture,
+    #[getset(get = "pub")]
+    specular: wgpu::Texture,
+    #[getset(get = "pub")]
+    irradiance: wgpu::Texture,
+    /// Scale and bias of F0 by the angle between normal and view and the roughness
+    #[getset(get = "pub")]
+    brdf_lut: wgpu::Texture,
+    cube_view: wgpu::TextureView,
+    specular_view: wgpu::TextureView,
+    irradiance_view: wgpu::TextureView,
+    brdf_lut_view: wgpu::TextureView,
+    #[getset(get = "pub")]
+    sampler: wgpu::Sampler,
+    /// The intensity and mip count read by `pbr.wgsl` and `skybox.wgsl`
+    #[getset(get = "pub")]
+    uniform: wgpu::Buffer,
+    skybox_pipeline: Arc<wgpu::RenderPipeline>,
+    skybox_bind_group: BindGroupInfo,
+}
+
+impl Environment {
+    pub const CUBE_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+    pub const BRDF_LUT_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rg16Float;
+
+    /// Loads the image of [`EnvironmentConfig::path`] and prefilters it
+    pub fn new(config: &Configuration) -> Result<Self, image::ImageError> {
+        let settings = &config.settings.environment;
+        let image = match &settings.path {
+            Some(path) => {
+                info!("Loading environment {}", path.display());
+                Some(image::open(path)?.into_rgba32f())
+            }
+            None => None,
+        };
+
+        let max_dimension = config.device.limits().max_texture_dimension_2d;
+        let resolution = |resolution: u32| {
+            if image.is_some() {
+                resolution.clamp(1, max_dimension)
+            } else {
+                1
+            }
+        };
+        let cube_resolution = resolution(settings.cube_resolution);
+        let specular_resolution = resolution(settings.specular_resolution);
+        let specular_mips = SPECULAR_MIPS.min(specular_resolution.ilog2() + 1);
+
+        let cube = create_cube(
+            config,
+            "Environment cube",
+            cube_resolution,
+            cube_resolution.ilog2() + 1,
+        );
+        let specular = create_cube(
+            config,
+            "Environment specular",
+            specular_resolution,
+            specular_mips,
+        );
+        let irradiance = create_cube(
+            config,
+            "Environment irradiance",
+            resolution(IRRADIANCE_RESOLUTION),
+            1,
+        );
+
+        let lut_resolution = resolution(BRDF_LUT_RESOLUTION);
+        let brdf_lut = config.device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("BRDF lookup table"),
+            size: wgpu::Extent3d {
+                width: lut_resolution,
+                height: lut_resolution,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: Self::BRDF_LUT_FORMAT,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::RENDER_ATTACHMENT,
+            view_formats: &[],
+        });
+
+        let sampler = config.device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("Environment sampler"),
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            mipmap_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+
+        let cube_view = create_cube_view(&cube);
+        let uniform = create_uniform_buffer(
+            config,
+            "Environment uniform",
+            &Vec4::new(
+                settings.intensity,
+                (specular_mips - 1) as f32,
+                f32::from_bits(image.is_some() as u32),
+                0.0,
+            )
+            .bytes(),
+        );
+
+        let skybox_bind_group = create_bindgroup(
+            config,
+            &[
+                BindGroupEntryInfo::new(
+                    wgpu::ShaderStages::FRAGMENT,
+                    uniform.as_entire_buffer_binding(),
+                ),
+                cube_entry(&cube_view),
+                BindGroupEntryInfo::new(wgpu::ShaderStages::FRAGMENT, &sampler),
+            ],
+            "Skybox",
+        );
+
+        // drawn first and without depth, the scene covers it
+        let mut depth_stencil = config.settings.depth.depth_stencil_state();
+        depth_stencil.depth_write_enabled = false;
+        depth_stencil.depth_compare = wgpu::CompareFunction::Always;
+
+        let skybox_pipeline = pipeline::create(
+            config,
+            &[],
+            &[&skybox_bind_group.layout, &Camera::layout(config)],
+            include_str!("skybox.wgsl"),
+            &PipelineDescriptor::new(config, wgpu::PrimitiveTopology::TriangleList)
+                .cull_mode(None)
+                .depth_stencil(Some(depth_stencil)),
+            Some("Skybox"),
+        );
+
+        let environment = Self {
+            loaded: image.is_some(),
+            specular_view: create_cube_view(&specular),
+            irradiance_view: create_cube_view(&irradiance),
+            brdf_lut_view: brdf_lut.create_view(&wgpu::TextureViewDescriptor::default()),
+            cube_view,
+            cube,
+            specular,
+            irradiance,
+            brdf_lut,
+            sampler,
+            uniform,
+            skybox_pipeline,
+            skybox_bind_group,
+        };
+
+        if let Some(image) = image {
+            environment.prefilter(config, image);
+        }
+
+        Ok(environment)
+    }
+
+    /// The entries of the light group shading with the environment, starting at binding 6
+    pub fn entries(&self) -> [BindGroupEntryInfo<'_>; 5] {
+        [
+            BindGroupEntryInfo::new(
+                wgpu::ShaderStages::FRAGMENT,
+                self.uniform.as_entire_buffer_binding(),
+            ),
+            cube_entry(&self.specular_view),
+            cube_entry(&self.irradiance_view),
+            BindGroupEntryInfo::new(wgpu::ShaderStages::FRAGMENT, &self.brdf_lut_view),
+            BindGroupEntryInfo::new(wgpu::ShaderStages::FRAGMENT, &self.sampler),
+        ]
+    }
+
+    /// Draws the environment behind the scene, if there is one.
+    /// <br>
+    /// Has to come first in the color pass, with the camera bound to group 1.
+    pub fn render_skybox<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>) {
+        if !self.loaded {
+            return;
+        }
+
+        render_pass.set_pipeline(&self.skybox_pipeline);
+        render_pass.set_bind_group(0, &self.skybox_bind_group.group, &[]);
+        render_pass.draw(0..3, 0..1);
+    }
+
+    /// Converts `image` into the cube and renders the specular mips, the irradiance and the BRDF
+    /// lookup table from it
+    fn prefilter(&self, config: &Configuration, image: Rgba32FImage) {
+        let max_dimension = config.device.limits().max_texture_dimension_2d;
+        let image = if image.width() > max_dimension || image.height() > max_dimension {
+            let scale = max_dimension as f32 / image.width().max(image.height()) as f32;
+            let width = ((image.width() as f32 * scale) as u32).max(1);
+            let height = ((image.height() as f32 * scale) as u32).max(1);
+            image::imageops::resize(&image, width, height, FilterType::Triangle)
+        } else {
+            image
+        };
+
+        let size = wgpu::Extent3d {
+            width: image.width(),
+            height: image.height(),
+            depth_or_array_layers: 1,
+        };
+        let equirect = config.device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Equirectangular environment"),
+            size,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::Rgba32Float,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            view_formats: &[],
+        });
+        let texels: Vec<u8> = image.iter().flat_map(|c| c.to_le_bytes()).collect();
+        config.queue.write_texture(
+            equirect.as_image_copy(),
+            &texels,
+            wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: Some(16 * size.width),
+                rows_per_image: Some(size.height),
+            },
+            size,
+        );
+        let equirect_view = equirect.create_view(&wgpu::TextureViewDescriptor::default());
+
+        let mut encoder = config
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("Environment encoder"),
+            });
+
+        for face in 0..6 {
+            let uniform = FilterUniform::new(face, 0.0, 0.0, 0);
+            let equirect = BindGroupEntryInfo::new(wgpu::ShaderStages::FRAGMENT, &equirect_view)
+                .texture(
+                    wgpu::TextureViewDimension::D2,
+                    wgpu::TextureSampleType::Float { filterable: false },
+                );
+
+            filter(
+                config,
+                &mut encoder,
+                include_str!("equirect.wgsl"),
+                "fs_main",
+                (&self.cube, face, 0),
+                uniform,
+                vec![equirect],
+            );
+        }
+
+        // the prefilters read coarser mips for sparse samples
+        MipGenerator::new(config).generate(config, &mut encoder, &self.cube);
+
+        let source_resolution = self.cube.width() as f32;
+        let source = || {
+            vec![
+                cube_entry(&self.cube_view),
+                BindGroupEntryInfo::new(wgpu::ShaderStages::FRAGMENT, &self.sampler),
+            ]
+        };
+
+        let mips = self.specular.mip_level_count();
+        for face in 0..6 {
+            for level in 0..mips {
+                let roughness = level as f32 / (mips - 1).max(1) as f32;
+                let uniform =
+                    FilterUniform::new(face, roughness, source_resolution, SPECULAR_SAMPLES);
+
+                filter(
+                    config,
+                    &mut encoder,
+                    include_str!("ibl.wgsl"),
+                    "fs_specular",
+                    (&self.specular, face, level),
+                    uniform,
+                    source(),
+                );
+            }
+
+            let uniform = FilterUniform::new(face, 1.0, source_resolution, IRRADIANCE_SAMPLES);
+            filter(
+                config,
+                &mut encoder,
+                include_str!("ibl.wgsl"),
+                "fs_irradiance",
+                (&self.irradiance, face, 0),
+                uniform,
+                source(),
+            );
+        }
+
+        filter(
+            config,
+            &mut encoder,
+            include_str!("ibl.wgsl"),
+            "fs_brdf_lut",
+            (&self.brdf_lut, 0, 0),
+            FilterUniform::new(0, 0.0, 0.0, BRDF_LUT_SAMPLES),
+            Vec::new(),
+        );
+
+        config.queue.submit(Some(encoder.finish()));
+    }
+}
+
+/// `Params` of `ibl.wgsl` and `equirect.wgsl`
+struct FilterUniform {
+    face: u32,
+    roughness: f32,
+    source_resolution: f32,
+    sample_count: u32,
+}
+
+impl FilterUniform {
+    fn new(face: u32, roughness: f32, source_resolution: f32, sample_count: u32) -> Self {
+        Self {
+            face,
+            roughness,
+            source_resolution,
+            sample_count,
+        }
+    }
+
+    fn bytes(&self) -> Vec<u8> {
+        Vec4::new(
+            f32::from_bits(self.face),
+            self.roughness,
+            self.source_resolution,
+            f32::from_bits(self.sample_count),
+        )
+        .bytes()
+    }
+}
+
+/// Renders the fragment `entry_point` of `shader` over a whole `(texture, layer, mip)`, with
+/// `uniform` at binding 0 followed by `resources`
+fn filter(
+    config: &Configuration,
+    encoder: &mut wgpu::CommandEncoder,
+    shader: &str,
+    entry_point: &str,
+    (texture, layer, level): (&wgpu::Texture, u32, u32),
+    uniform: FilterUniform,
+    resources: Vec<BindGroupEntryInfo>,
+) {
+    let buffer = create_uniform_buffer(config, "Environment filter", &uniform.bytes());
+    let entries: Vec<_> = Some(BindGroupEntryInfo::new(
+        wgpu::ShaderStages::FRAGMENT,
+        buffer.as_entire_buffer_binding(),
+    ))
+    .into_iter()
+    .chain(resources)
+    .collect();
+    let bind_group = create_bindgroup(config, &entries, "Environment filter");
+
+    let format = texture.format();
+    let pipeline = pipeline::create(
+        config,
+        &[],
+        &[&bind_group.layout],
+        shader,
+        &PipelineDescriptor::new(config, wgpu::PrimitiveTopology::TriangleList)
+            .cull_mode(None)
+            .depth_stencil(None)
+            .targets(vec![Some(format.into())])
+            .entry_points("vs_main", Some(entry_point)),
+        Some(&format!("Environment {entry_point}")),
+    );
+
+    let target = texture.create_view(&wgpu::TextureViewDescriptor {
+        dimension: Some(wgpu::TextureViewDimension::D2),
+        base_mip_level: level,
+        mip_level_count: Some(1),
+        base_array_layer: layer,
+        array_layer_count: Some(1),
+        ..Default::default()
+    });
+
+    let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+        label: Some("Environment filter pass"),
+        color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+            view: &target,
+            resolve_target: None,
+            ops: wgpu::Operations {
+                load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+                store: wgpu::StoreOp::Store,
+            },
+        })],
+        depth_stencil_attachment: None,
+        timestamp_writes: None,
+        occlusion_query_set: None,
+    });
+
+    pass.set_pipeline(&pipeline);
+    pass.set_bind_group(0, &bind_group.group, &[]);
+    pass.draw(0..3, 0..1);
+}
+
+fn create_cube(
+    config: &Configuration,
+    label: &str,
+    resolution: u32,
+    mip_level_count: u32,
+) -> wgpu::Texture {
+    config.device.create_texture(&wgpu::TextureDescriptor {
+        label: Some(label),
+        size: wgpu::Extent3d {
+            width: resolution,
+            height: resolution,
+            depth_or_array_layers: 6,
+        },
+        mip_level_count,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: Environment::CUBE_FORMAT,
+        usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::RENDER_ATTACHMENT,
+        view_formats: &[],
+    })
+}
+
+fn create_cube_view(texture: &wgpu::Texture) -> wgpu::TextureView {
+    texture.create_view(&wgpu::TextureViewDescriptor {
+        dimension: Some(wgpu::TextureViewDimension::Cube),
+        ..Default::default()
+    })
+}
+
+fn cube_entry(view: &wgpu::TextureView) -> BindGroupEntryInfo<'_> {
+    BindGroupEntryInfo::new(wgpu::ShaderStages::FRAGMENT, view).texture(
+        wgpu::TextureViewDimension::Cube,
+        wgpu::TextureSampleType::Float { filterable: true },
+    )
+}
@@ -0,0 +1,87 @@
+// Renders one face of the environment cube from an equirectangular image
+
+const PI: f32 = 3.14159265359;
+
+// Same as in `ibl.wgsl`
+struct Params {
+    face: u32,
+    roughness: f32,
+    source_resolution: f32,
+    sample_count: u32,
+}
+
+@group(0) @binding(0)
+var<uniform> params: Params;
+// 32 bit float textures can't be filtered on every device, so it is read texel by texel
+@group(0) @binding(1)
+var equirect: texture_2d<f32>;
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) tex_coords: vec2<f32>,
+}
+
+// a single triangle covering the whole target
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> VertexOutput {
+    var out: VertexOutput;
+
+    let tex_coords = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+
+    out.clip_position = vec4<f32>(tex_coords * vec2<f32>(2.0, -2.0) + vec2<f32>(-1.0, 1.0), 0.0, 1.0);
+    out.tex_coords = tex_coords;
+    return out;
+}
+
+// The direction through `tex_coords` of a cube face, in the order and orientation cube maps are
+// sampled with
+fn cube_direction(face: u32, tex_coords: vec2<f32>) -> vec3<f32> {
+    let uv = tex_coords * 2.0 - 1.0;
+
+    switch face {
+        case 0u: { return normalize(vec3<f32>(1.0, -uv.y, -uv.x)); }
+        case 1u: { return normalize(vec3<f32>(-1.0, -uv.y, uv.x)); }
+        case 2u: { return normalize(vec3<f32>(uv.x, 1.0, uv.y)); }
+        case 3u: { return normalize(vec3<f32>(uv.x, -1.0, -uv.y)); }
+        case 4u: { return normalize(vec3<f32>(uv.x, -uv.y, 1.0)); }
+        default: { return normalize(vec3<f32>(-uv.x, -uv.y, -1.0)); }
+    }
+}
+
+// Bilinear sample of the image in `direction`, the center column looks along -z
+fn sample_equirect(direction: vec3<f32>) -> vec3<f32> {
+    let size = vec2<i32>(textureDimensions(equirect));
+    let uv = vec2<f32>(
+        0.5 + atan2(direction.x, -direction.z) / (2.0 * PI),
+        acos(clamp(direction.y, -1.0, 1.0)) / PI,
+    );
+
+    let texel = uv * vec2<f32>(size) - 0.5;
+    let base = floor(texel);
+    let weight = texel - base;
+
+    // wraps around horizontally, the poles are clamped
+    let x0 = (i32(base.x) % size.x + size.x) % size.x;
+    let x1 = (x0 + 1) % size.x;
+    let y0 = clamp(i32(base.y), 0, size.y - 1);
+    let y1 = clamp(i32(base.y) + 1, 0, size.y - 1);
+
+    let top = mix(
+        textureLoad(equirect, vec2<i32>(x0, y0), 0).rgb,
+        textureLoad(equirect, vec2<i32>(x1, y0), 0).rgb,
+        weight.x,
+    );
+    let bottom = mix(
+        textureLoad(equirect, vec2<i32>(x0, y1), 0).rgb,
+        textureLoad(equirect, vec2<i32>(x1, y1), 0).rgb,
+        weight.x,
+    );
+    return mix(top, bottom, weight.y);
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let color = sample_equirect(cube_direction(params.face, in.tex_coords));
+    // the largest half float, brighter texels like the sun would turn infinite
+    return vec4<f32>(min(color, vec3<f32>(65504.0)), 1.0);
+}
@@ -0,0 +1,190 @@
+use std::time::Instant;
+
+use crate::{
+    bindgroup::{create_bindgroup, BindGroupEntryInfo, BindGroupInfo},
+    math::vec::Vec4,
+    renderer::Configuration,
+    tonemap::HdrTarget,
+    uniform_buffer::create_uniform_buffer,
+};
+
+/// Bins of the luminance histogram, `BINS` in `exposure.wgsl`
+const BINS: u64 = 256;
+
+/// Width and height of the pixel tile metered by one workgroup
+const TILE_SIZE: u32 = 16;
+
+/// Meters the average luminance of the [`HdrTarget`] every frame and adapts the exposure of the
+/// tonemapping to it.
+/// <br>
+/// A compute pass bins the log luminance of every pixel into a histogram, a second one averages
+/// it and moves the exposure buffer towards the result. Nothing is read back to the CPU, the
+/// tonemapping reads the buffer directly.
+pub struct AutoExposure {
+    /// Luminance range and adaptation of `exposure.wgsl`, rewritten every frame
+    uniform: wgpu::Buffer,
+    histogram: wgpu::Buffer,
+    histogram_pipeline: wgpu::ComputePipeline,
+    average_pipeline: wgpu::ComputePipeline,
+    bind_group: BindGroupInfo,
+    /// Size of the metered target
+    size: (u32, u32),
+    /// The first frame is exposed right away, the following ones adapt
+    last_frame: Option<Instant>,
+}
+
+impl AutoExposure {
+    /// Meters `target` into `exposure`, the buffer the tonemapping reads
+    pub fn new(config: &Configuration, target: &HdrTarget, exposure: &wgpu::Buffer) -> Self {
+        let uniform = create_uniform_buffer(
+            config,
+            "Metering uniform",
+            &MeteringUniform::new(config, 1.0).bytes(),
+        );
+
+        let histogram = config.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Luminance histogram"),
+            size: BINS * 4,
+            usage: wgpu::BufferUsages::STORAGE,
+            mapped_at_creation: false,
+        });
+
+        let bind_group = Self::bind_group(config, target, &uniform, &histogram, exposure);
+
+        let shader = config
+            .device
+            .create_shader_module(wgpu::include_wgsl!("exposure.wgsl"));
+        let layout = config
+            .device
+            .create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("Auto exposure"),
+                bind_group_layouts: &[&bind_group.layout],
+                push_constant_ranges: &[],
+            });
+
+        // the pipeline cache only holds render pipelines, these two exist once per renderer
+        let compute_pipeline = |entry_point| {
+            config
+                .device
+                .create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                    label: Some(entry_point),
+                    layout: Some(&layout),
+                    module: &shader,
+                    entry_point,
+                    compilation_options: Default::default(),
+                })
+        };
+
+        Self {
+            uniform,
+            histogram,
+            histogram_pipeline: compute_pipeline("cs_histogram"),
+            average_pipeline: compute_pipeline("cs_average"),
+            bind_group,
+            size: (target.texture().width(), target.texture().height()),
+            last_frame: None,
+        }
+    }
+
+    /// Meters the new `target` after a resize, keeping the adapted exposure
+    pub fn resize(&mut self, config: &Configuration, target: &HdrTarget, exposure: &wgpu::Buffer) {
+        self.bind_group =
+            Self::bind_group(config, target, &self.uniform, &self.histogram, exposure);
+        self.size = (target.texture().width(), target.texture().height());
+    }
+
+    /// Records the passes metering the target and adapting the exposure, by the time passed since
+    /// the last frame
+    pub fn meter(&mut self, config: &Configuration, encoder: &mut wgpu::CommandEncoder) {
+        let now = Instant::now();
+        let adaptation = self.last_frame.map_or(1.0, |last| {
+            let seconds = now.duration_since(last).as_secs_f32();
+            1.0 - (-seconds * config.settings.tonemap.adaptation_speed).exp()
+        });
+        self.last_frame = Some(now);
+
+        config.queue.write_buffer(
+            &self.uniform,
+            0,
+            &MeteringUniform::new(config, adaptation).bytes(),
+        );
+
+        // separate passes, so that the average sees the whole histogram
+        let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some("Histogram Pass"),
+            timestamp_writes: None,
+        });
+        pass.set_pipeline(&self.histogram_pipeline);
+        pass.set_bind_group(0, &self.bind_group.group, &[]);
+        pass.dispatch_workgroups(
+            self.size.0.div_ceil(TILE_SIZE),
+            self.size.1.div_ceil(TILE_SIZE),
+            1,
+        );
+        drop(pass);
+
+        let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some("Exposure Pass"),
+            timestamp_writes: None,
+        });
+        pass.set_pipeline(&self.average_pipeline);
+        pass.set_bind_group(0, &self.bind_group.group, &[]);
+        pass.dispatch_workgroups(1, 1, 1);
+    }
+
+    fn bind_group(
+        config: &Configuration,
+        target: &HdrTarget,
+        uniform: &wgpu::Buffer,
+        histogram: &wgpu::Buffer,
+        exposure: &wgpu::Buffer,
+    ) -> BindGroupInfo {
+        create_bindgroup(
+            config,
+            &[
+                BindGroupEntryInfo::new(
+                    wgpu::ShaderStages::COMPUTE,
+                    uniform.as_entire_buffer_binding(),
+                ),
+                BindGroupEntryInfo::new(wgpu::ShaderStages::COMPUTE, target.view()).texture(
+                    wgpu::TextureViewDimension::D2,
+                    wgpu::TextureSampleType::Float { filterable: false },
+                ),
+                BindGroupEntryInfo::new(
+                    wgpu::ShaderStages::COMPUTE,
+                    histogram.as_entire_buffer_binding(),
+                )
+                .storage(false),
+                BindGroupEntryInfo::new(
+                    wgpu::ShaderStages::COMPUTE,
+                    exposure.as_entire_buffer_binding(),
+                )
+                .storage(false),
+            ],
+            "Auto exposure",
+        )
+    }
+}
+
+/// `MeteringUniform` of `exposure.wgsl`
+struct MeteringUniform {
+    min_ev: f32,
+    range_ev: f32,
+    adaptation: f32,
+}
+
+impl MeteringUniform {
+    fn new(config: &Configuration, adaptation: f32) -> Self {
+        let settings = &config.settings.tonemap;
+
+        Self {
+            min_ev: settings.min_luminance,
+            range_ev: (settings.max_luminance - settings.min_luminance).max(0.001),
+            adaptation,
+        }
+    }
+
+    fn bytes(&self) -> Vec<u8> {
+        Vec4::new(self.min_ev, self.range_ev, self.adaptation, 0.0).bytes()
+    }
+}
@@ -0,0 +1,87 @@
+// Meters the HDR target for automatic exposure, with a histogram of the log luminance of every
+// pixel which is reduced to its average and adapted over time
+// https://bruop.github.io/exposure/
+
+const BINS: u32 = 256u;
+
+// See `MeteringUniform` in `exposure.rs`
+struct MeteringUniform {
+    // log2 luminance of the first and the range of all but the first bin, which counts black
+    min_ev: f32,
+    range_ev: f32,
+    // how far the exposure moves towards the metered one this frame, 1 jumps there
+    adaptation: f32,
+}
+
+// Same as in `tonemap.wgsl`
+struct Exposure {
+    // adapted average log2 luminance
+    luminance_ev: f32,
+}
+
+@group(0) @binding(0)
+var<uniform> metering: MeteringUniform;
+@group(0) @binding(1)
+var hdr: texture_2d<f32>;
+@group(0) @binding(2)
+var<storage, read_write> histogram: array<atomic<u32>, BINS>;
+@group(0) @binding(3)
+var<storage, read_write> exposure: Exposure;
+
+var<workgroup> local_bins: array<atomic<u32>, BINS>;
+var<workgroup> weighted: array<f32, BINS>;
+
+fn bin(color: vec3<f32>) -> u32 {
+    let luminance = dot(color, vec3<f32>(0.2126, 0.7152, 0.0722));
+    if luminance < 0.00001 {
+        return 0u;
+    }
+
+    let t = clamp((log2(luminance) - metering.min_ev) / metering.range_ev, 0.0, 1.0);
+    return 1u + u32(t * f32(BINS - 2u));
+}
+
+// Counts a 16x16 tile into workgroup memory first, which keeps the global atomics few
+@compute @workgroup_size(16, 16)
+fn cs_histogram(
+    @builtin(global_invocation_id) id: vec3<u32>,
+    @builtin(local_invocation_index) index: u32,
+) {
+    atomicStore(&local_bins[index], 0u);
+    workgroupBarrier();
+
+    if all(id.xy < textureDimensions(hdr)) {
+        atomicAdd(&local_bins[bin(textureLoad(hdr, id.xy, 0).rgb)], 1u);
+    }
+    workgroupBarrier();
+
+    atomicAdd(&histogram[index], atomicLoad(&local_bins[index]));
+}
+
+// Averages the histogram without the black bin and clears it for the next frame
+@compute @workgroup_size(256)
+fn cs_average(@builtin(local_invocation_index) index: u32) {
+    let count = atomicExchange(&histogram[index], 0u);
+    weighted[index] = f32(count) * f32(index);
+    workgroupBarrier();
+
+    for (var stride = BINS / 2u; stride > 0u; stride >>= 1u) {
+        if index < stride {
+            weighted[index] += weighted[index + stride];
+        }
+        workgroupBarrier();
+    }
+
+    if index == 0u {
+        let size = textureDimensions(hdr);
+        // `count` is the black bin in the first invocation
+        let lit = f32(size.x * size.y) - f32(count);
+        if lit < 1.0 {
+            return;
+        }
+
+        let average = (weighted[0] / lit - 1.0) / f32(BINS - 2u);
+        let metered = metering.min_ev + average * metering.range_ev;
+        exposure.luminance_ev = mix(exposure.luminance_ev, metered, metering.adaptation);
+    }
+}
@@ -1,75 +1,641 @@
-use std::io::Cursor;
+use std::{fmt, path::Path, sync::Arc};
 
-use getset::Getters;
-use gltf::{camera, Gltf};
+use getset::{CopyGetters, Getters};
+use gltf::{buffer, image, material::AlphaMode, Document, Gltf};
+use log::info;
 
 use crate::{
-    camera::{Camera, CameraData},
-    math::{transform::Transform, vec::Vec4},
+    camera::{Camera, CameraData, Projection},
+    environment::Environment,
+    light::{LightData, Lights},
+    material::{Material, MaterialData},
+    math::{mat::Mat4, vec::Vec3},
     mesh::Mesh,
     model_buffer_info::ModelBufferIndo,
+    node::Node,
     renderer::Configuration,
+    texture::Textures,
+    transforms::TransformStorage,
 };
 
-#[derive(Getters)]
+/// Everything that can go wrong while turning a glTF file into a [`Graph`]
+#[derive(Debug)]
+pub enum GraphError {
+    /// The file could not be read, parsed or one of its buffers / images could not be resolved
+    Import(gltf::Error),
+    /// The file neither has a default scene nor any scene at all
+    NoScene,
+    /// The file has no camera to look through
+    NoCamera,
+    /// The camera is valid glTF, but can't be used by the renderer
+    UnsupportedCamera(String),
+    /// An accessor reads outside of its buffer or is used in a way the renderer can't upload
+    InvalidAccessor(String),
+    /// An image has no pixels, or fewer than its size says
+    InvalidImage(String),
+    /// A node has more than one parent, or is its own ancestor
+    InvalidHierarchy(String),
+}
+
+impl fmt::Display for GraphError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GraphError::Import(err) => write!(f, "failed to import gltf: {err}"),
+            GraphError::NoScene => write!(f, "there needs to be at least one scene"),
+            GraphError::NoCamera => write!(f, "there needs to be at least one camera"),
+            GraphError::UnsupportedCamera(reason) => write!(f, "unsupported camera: {reason}"),
+            GraphError::InvalidAccessor(reason) => write!(f, "invalid accessor: {reason}"),
+            GraphError::InvalidImage(reason) => write!(f, "invalid image: {reason}"),
+            GraphError::InvalidHierarchy(reason) => write!(f, "invalid node hierarchy: {reason}"),
+        }
+    }
+}
+
+impl std::error::Error for GraphError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            GraphError::Import(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<gltf::Error> for GraphError {
+    fn from(err: gltf::Error) -> Self {
+        GraphError::Import(err)
+    }
+}
+
+/// A primitive of a mesh, the unit draws are sorted by
+#[derive(Clone, Copy, Debug)]
+pub struct Draw {
+    pub mesh: usize,
+    pub primitive: usize,
+}
+
+/// Draws split by the alpha mode of their material, rendered in field order
+#[derive(Clone, Debug, Default)]
+pub struct DrawQueues {
+    /// Sorted by pipeline and material to minimize state changes
+    pub opaque: Vec<Draw>,
+    /// Alpha tested, sorted like `opaque`
+    pub masked: Vec<Draw>,
+    /// Blended, sorted back to front every frame
+    pub transparent: Vec<Draw>,
+}
+
+impl DrawQueues {
+    pub fn iter(&self) -> impl Iterator<Item = &Draw> {
+        self.opaque
+            .iter()
+            .chain(&self.masked)
+            .chain(&self.transparent)
+    }
+
+    /// The draws casting shadows, transparent ones don't
+    pub fn casters(&self) -> impl Iterator<Item = &Draw> {
+        self.opaque.iter().chain(&self.masked)
+    }
+
+    pub fn len(&self) -> usize {
+        self.opaque.len() + self.masked.len() + self.transparent.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+#[derive(Getters, CopyGetters)]
 pub struct Graph {
     #[getset(get = "pub")]
     buffer_info: ModelBufferIndo,
+    /// All nodes of the document, indexed by their glTF index
+    #[getset(get = "pub")]
+    nodes: Vec<Node>,
+    /// The top level nodes of the rendered scene
+    #[getset(get = "pub")]
+    roots: Vec<usize>,
+    /// The world transforms of all nodes on the GPU
+    #[getset(get = "pub")]
+    transforms: TransformStorage,
     #[getset(get = "pub")]
     meshes: Vec<Mesh>,
+    /// Every primitive of every mesh
+    #[getset(get = "pub")]
+    draws: DrawQueues,
+    /// The materials of the document, followed by the default material
+    #[getset(get = "pub")]
+    materials: Vec<Material>,
+    #[getset(get = "pub")]
+    textures: Textures,
     #[getset(get = "pub")]
     camera: Camera,
+    /// The node the camera is attached to, moving it moves the camera
+    #[getset(get_copy = "pub")]
+    camera_node: usize,
+    /// The punctual lights of the scene, placed by their nodes
+    #[getset(get = "pub")]
+    lights: Lights,
 }
 
 impl Graph {
-    ///Create a graph of all scenes
-    pub fn create(config: &Configuration) -> Graph {
-        let gltf = Gltf::from_reader(Cursor::new(include_bytes!("gltf/scenes.gltf")))
-            .expect("failed to import gltf!");
+    /// Create a graph of the scene bundled with the binary, lit by `environment`
+    pub fn create(config: &Configuration, environment: &Environment) -> Result<Graph, GraphError> {
+        let gltf = Gltf::from_slice(include_bytes!("gltf/scenes.gltf"))?;
 
-        let raw_buffer_data = include_bytes!("gltf/scenes.bin").to_vec();
+        // the bundled scene references `scenes.bin`, which can't be resolved without a path
+        let buffers = vec![buffer::Data(include_bytes!("gltf/scenes.bin").to_vec())];
+        let images = gltf::import_images(&gltf.document, None, &buffers)?;
 
-        let buffer_info = ModelBufferIndo::new(config, &gltf, &raw_buffer_data);
+        Self::from_document(config, &gltf.document, &buffers, &images, environment)
+    }
+
+    /// Load a graph from a `.gltf` or `.glb` file at runtime.
+    /// <br>
+    /// External buffers and images are resolved relative to `path`, embedded base64 data URIs and
+    /// the binary chunk of `.glb` containers are supported as well.
+    pub fn load(
+        config: &Configuration,
+        path: impl AsRef<Path>,
+        environment: &Environment,
+    ) -> Result<Graph, GraphError> {
+        let (document, buffers, images) = gltf::import(path)?;
+
+        Self::from_document(config, &document, &buffers, &images, environment)
+    }
 
-        let mut meshes = Vec::new();
+    fn from_document(
+        config: &Configuration,
+        document: &Document,
+        buffers: &[buffer::Data],
+        images: &[image::Data],
+        environment: &Environment,
+    ) -> Result<Graph, GraphError> {
+        let buffer_info = ModelBufferIndo::new(config, document, buffers)?;
 
-        let scene = gltf
+        let scene = document
             .default_scene()
-            .expect("There needs to be a default scene!");
+            .or_else(|| document.scenes().next())
+            .ok_or(GraphError::NoScene)?;
 
-        let camera = gltf
-            .cameras()
-            .next()
-            .expect("There needs to be at least one camera!");
+        let parents = parents(document)?;
 
-        let projection = match camera.projection() {
-            gltf::camera::Projection::Orthographic(_) => {
-                todo!("Orthographic Cameras not yet supported")
-            }
-            gltf::camera::Projection::Perspective(p) => p,
-        };
-        let camera_data = CameraData::new(
-            projection.znear(),
-            projection
-                .zfar()
-                .expect("Perspective camera needs to have a z-far"),
-            projection.yfov(),
-            Default::default(),
-        );
+        let mut nodes: Vec<_> = document
+            .nodes()
+            .map(|node| Node::new(&node, parents[node.index()]))
+            .collect();
 
-        let camera = Camera::new(config, camera_data, camera.name().unwrap_or("Camera"));
+        let roots: Vec<_> = scene.nodes().map(|node| node.index()).collect();
 
-        for node in scene.nodes() {
-            if let Some(mesh) = node.mesh() {
-                let mesh = Mesh::new(config, &camera, mesh, node, &buffer_info);
-                meshes.push(mesh);
-            }
+        for &root in &roots {
+            propagate(&mut nodes, root, &Mat4::default(), false, &mut |_| {});
+        }
+
+        // only the nodes reachable from the scene are drawn
+        let mut reachable = Vec::new();
+        let mut stack: Vec<_> = scene.nodes().collect();
+        while let Some(node) = stack.pop() {
+            stack.extend(node.children());
+            reachable.push(node);
         }
 
-        Graph {
+        // the first camera in the scene is the one to look through
+        let camera = reachable
+            .iter()
+            .find_map(|node| node.camera().map(|camera| (node, camera)))
+            .ok_or(GraphError::NoCamera)?;
+        let camera_node = camera.0.index();
+        let camera = create_camera(config, camera.1, &nodes[camera_node].world())?;
+
+        let textures = Textures::new(config, document, images)?;
+
+        let mut materials: Vec<_> = document
+            .materials()
+            .map(|material| {
+                Material::new(
+                    config,
+                    MaterialData::from(&material),
+                    &textures,
+                    material.name().unwrap_or("Unnamed Material"),
+                )
+            })
+            .collect();
+        materials.push(Material::new(
+            config,
+            MaterialData::default(),
+            &textures,
+            "Default Material",
+        ));
+
+        let transforms = TransformStorage::new(config, &nodes);
+
+        let lights = reachable
+            .iter()
+            .filter_map(|node| {
+                node.light()
+                    .map(|light| (node.index(), LightData::from(&light)))
+            })
+            .collect();
+        let lights = Lights::new(config, lights, &nodes, environment);
+
+        let bind_group_layouts: [&wgpu::BindGroupLayout; 3] = [
+            &transforms.bind_group().layout,
+            &camera.bind_group().layout,
+            &lights.bind_group().layout,
+        ];
+        let shadow_bind_group_layouts: [&wgpu::BindGroupLayout; 2] = [
+            &transforms.bind_group().layout,
+            &lights.shadows().bind_group().layout,
+        ];
+        let meshes: Vec<_> = reachable
+            .iter()
+            .filter_map(|node| {
+                node.mesh().map(|mesh| {
+                    Mesh::new(
+                        config,
+                        mesh,
+                        node,
+                        &bind_group_layouts,
+                        &shadow_bind_group_layouts,
+                        &buffer_info,
+                        &materials,
+                    )
+                })
+            })
+            .collect();
+
+        let draws = sorted_draws(&meshes, &materials);
+        info!(
+            "{} draws sharing {} pipelines",
+            draws.len(),
+            config.pipeline_cache.len()
+        );
+
+        let mut graph = Graph {
             buffer_info,
+            nodes,
+            roots,
+            transforms,
             meshes,
+            draws,
+            materials,
+            textures,
             camera,
+            camera_node,
+            lights,
+        };
+        graph.sort_transparent();
+
+        Ok(graph)
+    }
+
+    /// Access a node to change its transform, the change is applied with the next [`Graph::update`]
+    pub fn node_mut(&mut self, index: usize) -> &mut Node {
+        &mut self.nodes[index]
+    }
+
+    /// Access the camera to move it or change its projection, using [`Camera::update`]
+    pub fn camera_mut(&mut self) -> &mut Camera {
+        &mut self.camera
+    }
+
+    /// Records all draws, the camera and the lights have to be bound to group 1 and 2 by the caller
+    pub fn render<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>) {
+        let mut pipeline = None;
+        let mut material = None;
+        let mut node = None;
+
+        for draw in self.draws.iter() {
+            let mesh = &self.meshes[draw.mesh];
+            let primitive = &mesh.primitives()[draw.primitive];
+
+            if pipeline != Some(Arc::as_ptr(primitive.pipeline())) {
+                pipeline = Some(Arc::as_ptr(primitive.pipeline()));
+                render_pass.set_pipeline(primitive.pipeline());
+            }
+
+            if material != Some(primitive.material()) {
+                material = Some(primitive.material());
+                render_pass.set_bind_group(
+                    3,
+                    &self.materials[primitive.material()].bind_group().group,
+                    &[],
+                );
+            }
+
+            if node != Some(mesh.node()) {
+                node = Some(mesh.node());
+                render_pass.set_bind_group(
+                    0,
+                    &self.transforms.bind_group().group,
+                    &[self.transforms.offset(mesh.node())],
+                );
+            }
+
+            primitive.draw(render_pass, &self.buffer_info);
+        }
+    }
+
+    /// Records the shadow casting draws into `view` of the [`crate::shadow::ShadowMaps`], a
+    /// cascade layer or an atlas tile
+    pub fn render_shadows<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>, view: usize) {
+        let shadows = self.lights.shadows();
+        render_pass.set_bind_group(1, &shadows.bind_group().group, &[shadows.offset(view)]);
+
+        let mut pipeline = None;
+        let mut node = None;
+
+        for draw in self.draws.casters() {
+            let mesh = &self.meshes[draw.mesh];
+            let primitive = &mesh.primitives()[draw.primitive];
+            let Some(shadow_pipeline) = primitive.shadow_pipeline() else {
+                continue;
+            };
+
+            if pipeline != Some(Arc::as_ptr(shadow_pipeline)) {
+                pipeline = Some(Arc::as_ptr(shadow_pipeline));
+                render_pass.set_pipeline(shadow_pipeline);
+            }
+
+            let material = &self.materials[primitive.material()];
+            if material.data().alpha_mode == AlphaMode::Mask {
+                render_pass.set_bind_group(2, &material.bind_group().group, &[]);
+            }
+
+            if node != Some(mesh.node()) {
+                node = Some(mesh.node());
+                render_pass.set_bind_group(
+                    0,
+                    &self.transforms.bind_group().group,
+                    &[self.transforms.offset(mesh.node())],
+                );
+            }
+
+            primitive.draw(render_pass, &self.buffer_info);
+        }
+    }
+
+    /// Recomputes the world transforms of all dirty subtrees and uploads them to the
+    /// [`TransformStorage`], the camera and the lights
+    pub fn update(&mut self, config: &Configuration) {
+        let mut changed = Vec::new();
+
+        for &root in &self.roots {
+            propagate(
+                &mut self.nodes,
+                root,
+                &Mat4::default(),
+                false,
+                &mut |index| changed.push(index),
+            );
+        }
+
+        if !changed.is_empty() {
+            changed.sort_unstable();
+            self.transforms.write(config, &self.nodes, &changed);
+
+            if changed.binary_search(&self.camera_node).is_ok() {
+                let mut data = self.camera.data().clone();
+                data.transform = self.nodes[self.camera_node].world().decompose();
+                self.camera.update(&config.queue, &data);
+            }
+        }
+
+        // the camera can also be moved without its node
+        self.sort_transparent();
+        let bounds = self.bounds();
+        self.lights
+            .update(config, &self.camera, &self.nodes, bounds);
+    }
+
+    /// A sphere around all shadow casting primitives in world space
+    fn bounds(&self) -> (Vec3, f32) {
+        let mut bounds: Option<(Vec3, f32)> = None;
+
+        for draw in self.draws.casters() {
+            let mesh = &self.meshes[draw.mesh];
+            let primitive = &mesh.primitives()[draw.primitive];
+            let world = self.nodes[mesh.node()].world();
+
+            let scale = [
+                Vec3::new(1.0, 0.0, 0.0),
+                Vec3::new(0.0, 1.0, 0.0),
+                Vec3::new(0.0, 0.0, 1.0),
+            ]
+            .map(|axis| world.transform_vector(axis).length())
+            .into_iter()
+            .fold(0.0, f32::max);
+            let sphere = (
+                world.transform_point(primitive.center()),
+                primitive.radius() * scale,
+            );
+
+            bounds = Some(match bounds {
+                Some(bounds) => merge_spheres(bounds, sphere),
+                None => sphere,
+            });
+        }
+
+        bounds.unwrap_or_default()
+    }
+
+    /// Orders the transparent draws back to front by the view depth of their centers
+    fn sort_transparent(&mut self) {
+        let view = self.camera.data().view();
+
+        let depth = |draw: &Draw| {
+            let mesh = &self.meshes[draw.mesh];
+            let center = mesh.primitives()[draw.primitive].center();
+            let world = self.nodes[mesh.node()].world();
+
+            // the camera looks along -z, so the farthest draw has the lowest z
+            (view * world).transform_point(center).z
+        };
+
+        let mut transparent = std::mem::take(&mut self.draws.transparent);
+        transparent.sort_by(|a, b| depth(a).total_cmp(&depth(b)));
+        self.draws.transparent = transparent;
+    }
+}
+
+/// All primitives, queued by alpha mode and grouped by pipeline and then by material
+fn sorted_draws(meshes: &[Mesh], materials: &[Material]) -> DrawQueues {
+    let mut queues = DrawQueues::default();
+
+    for (mesh, m) in meshes.iter().enumerate() {
+        for (primitive, p) in m.primitives().iter().enumerate() {
+            let queue = match materials[p.material()].data().alpha_mode {
+                AlphaMode::Opaque => &mut queues.opaque,
+                AlphaMode::Mask => &mut queues.masked,
+                AlphaMode::Blend => &mut queues.transparent,
+            };
+            queue.push(Draw { mesh, primitive });
+        }
+    }
+
+    let key = |draw: &Draw| {
+        let primitive = &meshes[draw.mesh].primitives()[draw.primitive];
+        (Arc::as_ptr(primitive.pipeline()), primitive.material())
+    };
+    queues.opaque.sort_by_key(key);
+    queues.masked.sort_by_key(key);
+
+    queues
+}
+
+/// The smallest sphere around two spheres
+fn merge_spheres((a, a_radius): (Vec3, f32), (b, b_radius): (Vec3, f32)) -> (Vec3, f32) {
+    let distance = (b - a).length();
+
+    if distance + b_radius <= a_radius {
+        (a, a_radius)
+    } else if distance + a_radius <= b_radius {
+        (b, b_radius)
+    } else {
+        let radius = (distance + a_radius + b_radius) * 0.5;
+        (a + (b - a) * ((radius - a_radius) / distance), radius)
+    }
+}
+
+/// Creates the camera looking from the world transform of its node
+fn create_camera(
+    config: &Configuration,
+    camera: gltf::Camera,
+    world: &Mat4,
+) -> Result<Camera, GraphError> {
+    let camera_data = match camera.projection() {
+        gltf::camera::Projection::Orthographic(o) => CameraData::new(
+            o.znear(),
+            o.zfar(),
+            Projection::Orthographic {
+                xmag: o.xmag(),
+                ymag: o.ymag(),
+            },
+            world.decompose(),
+        ),
+        gltf::camera::Projection::Perspective(p) => CameraData::new(
+            p.znear(),
+            p.zfar().ok_or_else(|| {
+                GraphError::UnsupportedCamera("perspective camera needs to have a z-far".into())
+            })?,
+            Projection::Perspective { fov: p.yfov() },
+            world.decompose(),
+        ),
+    };
+
+    Ok(Camera::new(
+        config,
+        camera_data,
+        camera.name().unwrap_or("Camera"),
+    ))
+}
+
+/// The parent of every node, checked to form a forest as the glTF spec requires <br>
+/// Every walk over the hierarchy relies on this, a cycle would recurse forever
+fn parents(document: &Document) -> Result<Vec<Option<usize>>, GraphError> {
+    let mut parents = vec![None; document.nodes().len()];
+    for node in document.nodes() {
+        for child in node.children() {
+            if let Some(parent) = parents[child.index()].replace(node.index()) {
+                return Err(GraphError::InvalidHierarchy(format!(
+                    "node {} is a child of both node {parent} and node {}",
+                    child.index(),
+                    node.index(),
+                )));
+            }
         }
     }
+
+    // with at most one parent each, any chain longer than the node count has to loop
+    for start in 0..parents.len() {
+        let mut current = start;
+        for _ in 0..parents.len() {
+            match parents[current] {
+                Some(parent) if parent == start => {
+                    return Err(GraphError::InvalidHierarchy(format!(
+                        "node {start} is its own ancestor"
+                    )))
+                }
+                Some(parent) => current = parent,
+                None => break,
+            }
+        }
+    }
+
+    Ok(parents)
+}
+
+/// Updates the world transform of `index` and its subtree if it, or one of its parents, is dirty
+fn propagate(
+    nodes: &mut [Node],
+    index: usize,
+    parent_world: &Mat4,
+    parent_changed: bool,
+    on_change: &mut impl FnMut(usize),
+) {
+    let node = &mut nodes[index];
+    let changed = parent_changed || node.dirty();
+
+    if changed {
+        node.propagate(parent_world);
+        on_change(index);
+    }
+
+    let world = node.world();
+    for i in 0..nodes[index].children().len() {
+        let child = nodes[index].children()[i];
+        propagate(nodes, child, &world, changed, on_change);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn document(nodes: &str) -> Document {
+        let json = format!(r#"{{ "asset": {{ "version": "2.0" }}, "nodes": [{nodes}] }}"#);
+
+        Gltf::from_slice(json.as_bytes())
+            .expect("document is valid")
+            .document
+    }
+
+    #[test]
+    fn accepts_a_forest() {
+        let document = document(r#"{ "children": [1, 2] }, {}, { "children": [3] }, {}, {}"#);
+
+        let parents = parents(&document).expect("hierarchy is valid");
+        assert_eq!(parents, [None, Some(0), Some(0), Some(2), None]);
+    }
+
+    #[test]
+    fn rejects_cycles() {
+        let document = document(r#"{ "children": [1] }, { "children": [2] }, { "children": [0] }"#);
+
+        assert!(matches!(
+            parents(&document),
+            Err(GraphError::InvalidHierarchy(_))
+        ));
+    }
+
+    #[test]
+    fn rejects_self_parents() {
+        let document = document(r#"{ "children": [0] }"#);
+
+        assert!(matches!(
+            parents(&document),
+            Err(GraphError::InvalidHierarchy(_))
+        ));
+    }
+
+    #[test]
+    fn rejects_shared_children() {
+        let document = document(r#"{ "children": [2] }, { "children": [2] }, {}"#);
+
+        assert!(matches!(
+            parents(&document),
+            Err(GraphError::InvalidHierarchy(_))
+        ));
+    }
 }
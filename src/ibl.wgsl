@@ -0,0 +1,176 @@
+// Prefilters the environment cube for image based lighting, following the split sum
+// approximation of https://cdn2.unrealengine.com/Resources/files/2013SiggraphPresentationsNotes-26915738.pdf
+
+const PI: f32 = 3.14159265359;
+
+// One face of one target mip, see `Environment::filter`
+struct Params {
+    face: u32,
+    // of the specular mip being filtered
+    roughness: f32,
+    // face size of the first mip of `source`, to pick the mip matching the footprint of a sample
+    source_resolution: f32,
+    sample_count: u32,
+}
+
+@group(0) @binding(0)
+var<uniform> params: Params;
+@group(0) @binding(1)
+var source: texture_cube<f32>;
+@group(0) @binding(2)
+var source_sampler: sampler;
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) tex_coords: vec2<f32>,
+}
+
+// a single triangle covering the whole target
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> VertexOutput {
+    var out: VertexOutput;
+
+    let tex_coords = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+
+    out.clip_position = vec4<f32>(tex_coords * vec2<f32>(2.0, -2.0) + vec2<f32>(-1.0, 1.0), 0.0, 1.0);
+    out.tex_coords = tex_coords;
+    return out;
+}
+
+// Same as in `equirect.wgsl`
+fn cube_direction(face: u32, tex_coords: vec2<f32>) -> vec3<f32> {
+    let uv = tex_coords * 2.0 - 1.0;
+
+    switch face {
+        case 0u: { return normalize(vec3<f32>(1.0, -uv.y, -uv.x)); }
+        case 1u: { return normalize(vec3<f32>(-1.0, -uv.y, uv.x)); }
+        case 2u: { return normalize(vec3<f32>(uv.x, 1.0, uv.y)); }
+        case 3u: { return normalize(vec3<f32>(uv.x, -1.0, -uv.y)); }
+        case 4u: { return normalize(vec3<f32>(uv.x, -uv.y, 1.0)); }
+        default: { return normalize(vec3<f32>(-uv.x, -uv.y, -1.0)); }
+    }
+}
+
+// Low discrepancy point `i` of `count` on the unit square
+fn hammersley(i: u32, count: u32) -> vec2<f32> {
+    return vec2<f32>(f32(i) / f32(count), f32(reverseBits(i)) * 2.3283064365386963e-10);
+}
+
+// Turns `v` given around +z into the space around `n`
+fn around(v: vec3<f32>, n: vec3<f32>) -> vec3<f32> {
+    let up = select(vec3<f32>(1.0, 0.0, 0.0), vec3<f32>(0.0, 0.0, 1.0), abs(n.z) < 0.999);
+    let tangent = normalize(cross(up, n));
+    let bitangent = cross(n, tangent);
+    return tangent * v.x + bitangent * v.y + n * v.z;
+}
+
+// A half vector around `n` distributed like the GGX lobe of `alpha`
+fn importance_sample_ggx(xi: vec2<f32>, n: vec3<f32>, alpha: f32) -> vec3<f32> {
+    let phi = 2.0 * PI * xi.x;
+    let cos_theta = sqrt((1.0 - xi.y) / (1.0 + (alpha * alpha - 1.0) * xi.y));
+    let sin_theta = sqrt(1.0 - cos_theta * cos_theta);
+    return normalize(around(vec3<f32>(sin_theta * cos(phi), sin_theta * sin(phi), cos_theta), n));
+}
+
+fn distribution_ggx(n_dot_h: f32, alpha: f32) -> f32 {
+    let alpha2 = alpha * alpha;
+    let d = n_dot_h * n_dot_h * (alpha2 - 1.0) + 1.0;
+    return alpha2 / (PI * d * d);
+}
+
+// The mip of `source` covering the solid angle of a sample drawn with `pdf`, which averages
+// what the sparse samples would miss instead of aliasing
+// https://developer.nvidia.com/gpugems/gpugems3/part-iii-rendering/chapter-20-gpu-based-importance-sampling
+fn source_mip(pdf: f32) -> f32 {
+    let texel = 4.0 * PI / (6.0 * params.source_resolution * params.source_resolution);
+    let sample = 1.0 / (f32(params.sample_count) * pdf + 0.0001);
+    return max(0.5 * log2(sample / texel) + 1.0, 0.0);
+}
+
+// A mip of the specular cube, assuming the view direction equals the normal
+@fragment
+fn fs_specular(in: VertexOutput) -> @location(0) vec4<f32> {
+    let n = cube_direction(params.face, in.tex_coords);
+
+    // mirror reflections are the environment itself
+    if params.roughness == 0.0 {
+        return textureSampleLevel(source, source_sampler, n, 0.0);
+    }
+
+    let alpha = params.roughness * params.roughness;
+    var color = vec3<f32>(0.0);
+    var weight = 0.0;
+
+    for (var i = 0u; i < params.sample_count; i++) {
+        let h = importance_sample_ggx(hammersley(i, params.sample_count), n, alpha);
+        let l = normalize(2.0 * dot(n, h) * h - n);
+        let n_dot_l = dot(n, l);
+
+        if n_dot_l > 0.0 {
+            // with the view along the normal, the pdf of `l` reduces to D / 4
+            let pdf = distribution_ggx(max(dot(n, h), 0.0), alpha) / 4.0;
+            color += textureSampleLevel(source, source_sampler, l, source_mip(pdf)).rgb * n_dot_l;
+            weight += n_dot_l;
+        }
+    }
+
+    return vec4<f32>(color / max(weight, 0.0001), 1.0);
+}
+
+// The irradiance arriving from the hemisphere around the normal divided by pi, so that a
+// lambertian surface reflects it times its albedo
+@fragment
+fn fs_irradiance(in: VertexOutput) -> @location(0) vec4<f32> {
+    let n = cube_direction(params.face, in.tex_coords);
+    var color = vec3<f32>(0.0);
+
+    // cosine weighted samples, their average is the convolution with the cosine lobe over pi
+    for (var i = 0u; i < params.sample_count; i++) {
+        let xi = hammersley(i, params.sample_count);
+        let phi = 2.0 * PI * xi.x;
+        let cos_theta = sqrt(1.0 - xi.y);
+        let sin_theta = sqrt(xi.y);
+        let l = normalize(around(vec3<f32>(sin_theta * cos(phi), sin_theta * sin(phi), cos_theta), n));
+
+        let pdf = cos_theta / PI;
+        color += textureSampleLevel(source, source_sampler, l, source_mip(pdf)).rgb;
+    }
+
+    return vec4<f32>(color / f32(params.sample_count), 1.0);
+}
+
+// Scale and bias of F0 for the specular term, by n_dot_v along x and roughness along y
+@fragment
+fn fs_brdf_lut(in: VertexOutput) -> @location(0) vec4<f32> {
+    let n_dot_v = max(in.tex_coords.x, 0.001);
+    let roughness = in.tex_coords.y;
+    let alpha = roughness * roughness;
+    // the geometry term of image based lighting remaps the roughness differently than for lights
+    let k = alpha / 2.0;
+
+    let v = vec3<f32>(sqrt(1.0 - n_dot_v * n_dot_v), 0.0, n_dot_v);
+    let n = vec3<f32>(0.0, 0.0, 1.0);
+    var scale = 0.0;
+    var bias = 0.0;
+
+    for (var i = 0u; i < params.sample_count; i++) {
+        let h = importance_sample_ggx(hammersley(i, params.sample_count), n, alpha);
+        let l = normalize(2.0 * dot(v, h) * h - v);
+        let n_dot_l = l.z;
+
+        if n_dot_l > 0.0 {
+            let n_dot_h = max(h.z, 0.0);
+            let v_dot_h = max(dot(v, h), 0.0);
+
+            let g = n_dot_v / (n_dot_v * (1.0 - k) + k) * n_dot_l / (n_dot_l * (1.0 - k) + k);
+            let visibility = g * v_dot_h / max(n_dot_h * n_dot_v, 0.0001);
+            let fresnel = pow(1.0 - v_dot_h, 5.0);
+
+            scale += (1.0 - fresnel) * visibility;
+            bias += fresnel * visibility;
+        }
+    }
+
+    let count = f32(params.sample_count);
+    return vec4<f32>(scale / count, bias / count, 0.0, 1.0);
+}
@@ -1,17 +1,99 @@
+use std::fmt;
+
+use crate::{cli::Options, renderer::RendererError};
+
 mod app;
-mod bindgroup;
-mod camera;
-mod graph;
-mod math;
-mod mesh;
-mod model_buffer_info;
-mod pipeline;
-mod primitive;
-mod renderer;
+pub mod bindgroup;
+pub mod camera;
+mod cli;
+pub mod depth;
+pub mod environment;
+pub mod exposure;
+pub mod graph;
+pub mod light;
+pub mod material;
+pub mod math;
+pub mod mesh;
+pub mod model_buffer_info;
+pub mod node;
+pub mod offscreen;
+pub mod pipeline;
+pub mod primitive;
+pub mod renderer;
+pub mod shadow;
+pub mod texture;
+pub mod tonemap;
+pub mod transforms;
 mod uniform_buffer;
-mod view;
+pub mod vertex;
+pub mod view;
+
+/// Everything that makes the binary exit unsuccessfully
+#[derive(Debug)]
+pub enum RunError {
+    /// The command line could not be parsed
+    Usage(String),
+    Renderer(RendererError),
+    EventLoop(winit::error::EventLoopError),
+    Window(winit::error::OsError),
+}
+
+impl fmt::Display for RunError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RunError::Usage(err) => write!(f, "{err}\n{}", cli::USAGE),
+            RunError::Renderer(err) => write!(f, "{err}"),
+            RunError::EventLoop(err) => write!(f, "event loop failed: {err}"),
+            RunError::Window(err) => write!(f, "failed to create the window: {err}"),
+        }
+    }
+}
+
+impl std::error::Error for RunError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            RunError::Usage(_) => None,
+            RunError::Renderer(err) => Some(err),
+            RunError::EventLoop(err) => Some(err),
+            RunError::Window(err) => Some(err),
+        }
+    }
+}
+
+impl From<RendererError> for RunError {
+    fn from(err: RendererError) -> Self {
+        RunError::Renderer(err)
+    }
+}
+
+impl From<winit::error::EventLoopError> for RunError {
+    fn from(err: winit::error::EventLoopError) -> Self {
+        RunError::EventLoop(err)
+    }
+}
+
+impl From<winit::error::OsError> for RunError {
+    fn from(err: winit::error::OsError) -> Self {
+        RunError::Window(err)
+    }
+}
+
+/// Opens a window rendering the glTF / GLB file given as first argument,
+/// falling back to the bundled scene.
+/// <br>
+/// With `--headless`, a single frame is written to the PNG given by `--output` instead.
+pub fn run() -> Result<(), RunError> {
+    // more beautiful logging
+    env_logger::init();
+
+    let options = Options::parse(std::env::args().skip(1)).map_err(RunError::Usage)?;
+
+    if options.headless {
+        pollster::block_on(app::capture(&options))?;
+    } else {
+        // create a window
+        pollster::block_on(app::init(&options))?;
+    }
 
-pub fn run() {
-    // create a window
-    pollster::block_on(app::init());
+    Ok(())
 }
@@ -0,0 +1,322 @@
+use getset::Getters;
+use gltf::khr_lights_punctual::Kind;
+use log::warn;
+
+use crate::{
+    bindgroup::{create_bindgroup, BindGroupEntryInfo, BindGroupInfo},
+    camera::Camera,
+    environment::Environment,
+    math::{mat::Mat4, vec::Vec3},
+    node::Node,
+    renderer::Configuration,
+    shadow::{LocalCaster, ShadowMaps, MAX_DIRECTIONAL_SHADOWS},
+};
+
+/// Size of `Light` in `pbr.wgsl`
+const LIGHT_SIZE: u64 = 64;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LightKind {
+    /// Shines along the -z axis of its node, `intensity` is in lux
+    Directional,
+    /// Shines in all directions, `intensity` is in candela
+    Point,
+    /// Shines along the -z axis of its node in a cone, `intensity` is in candela
+    Spot {
+        /// Angle from the axis where the falloff starts
+        inner_cone_angle: f32,
+        /// Angle from the axis where the falloff reaches zero
+        outer_cone_angle: f32,
+    },
+}
+
+/// A punctual light of `KHR_lights_punctual`, positioned by the node it is attached to
+#[derive(Clone, Debug)]
+pub struct LightData {
+    pub kind: LightKind,
+    /// Linear RGB
+    pub color: Vec3,
+    pub intensity: f32,
+    /// Distance at which the light reaches zero, infinite if `None`
+    pub range: Option<f32>,
+    /// Whether the light has a shadow map.
+    /// <br>
+    /// Read from `"castShadows"` in the extras of the light. If it is missing, directional lights
+    /// cast shadows, while the more expensive point and spot lights have to opt in.
+    pub cast_shadows: bool,
+}
+
+impl From<&gltf::khr_lights_punctual::Light<'_>> for LightData {
+    fn from(light: &gltf::khr_lights_punctual::Light) -> Self {
+        let kind = match light.kind() {
+            Kind::Directional => LightKind::Directional,
+            Kind::Point => LightKind::Point,
+            Kind::Spot {
+                inner_cone_angle,
+                outer_cone_angle,
+            } => LightKind::Spot {
+                inner_cone_angle,
+                outer_cone_angle,
+            },
+        };
+
+        let cast_shadows = light
+            .extras()
+            .as_ref()
+            .and_then(|extras| {
+                gltf::json::deserialize::from_str::<gltf::json::Value>(extras.get()).ok()
+            })
+            .and_then(|extras| extras.get("castShadows")?.as_bool())
+            .unwrap_or(kind == LightKind::Directional);
+
+        Self {
+            kind,
+            color: light.color().into(),
+            intensity: light.intensity(),
+            range: light.range(),
+            cast_shadows,
+        }
+    }
+}
+
+impl LightData {
+    /// Lights scenes without lights of their own with [`RenderSettings::fallback_light`](crate::renderer::RenderSettings::fallback_light), from
+    /// above and behind the default view
+    pub fn fallback() -> Self {
+        Self {
+            kind: LightKind::Directional,
+            color: Vec3::one(),
+            intensity: 3.0,
+            range: None,
+            cast_shadows: true,
+        }
+    }
+
+    /// The world transform of the fallback light
+    fn fallback_world() -> Mat4 {
+        Mat4::look_at(
+            Vec3::new(0.4, 0.8, 0.45),
+            Vec3::default(),
+            Vec3::new(0.0, 1.0, 0.0),
+        )
+        .inverse()
+        .unwrap_or_default()
+    }
+
+    /// The shadow of a point or spot light placed at `world`, `None` for directional lights
+    fn local_caster(&self, world: &Mat4, max_distance: f32) -> Option<LocalCaster> {
+        let position = world.transform_point(Vec3::default());
+        let range = self.range.unwrap_or(max_distance);
+
+        match self.kind {
+            LightKind::Directional => None,
+            LightKind::Point => Some(LocalCaster::Point { position, range }),
+            LightKind::Spot {
+                outer_cone_angle, ..
+            } => Some(LocalCaster::Spot {
+                position,
+                direction: direction(world),
+                outer_cone_angle,
+                range,
+            }),
+        }
+    }
+
+    /// The light placed at `world`, matching `Light` in `pbr.wgsl`
+    /// <br>
+    /// `shadow` is the first cascade layer of directional lights, or the first atlas tile of
+    /// point and spot lights.
+    pub fn bytes(&self, world: &Mat4, shadow: Option<usize>) -> Vec<u8> {
+        let position = world.transform_point(Vec3::default());
+        let direction = direction(world);
+
+        let (kind, spot_scale, spot_offset) = match self.kind {
+            LightKind::Directional => (0, 0.0, 0.0),
+            LightKind::Point => (1, 0.0, 0.0),
+            LightKind::Spot {
+                inner_cone_angle,
+                outer_cone_angle,
+            } => {
+                // the cone falloff as recommended by the extension, precomputed per light
+                let scale = 1.0 / (inner_cone_angle.cos() - outer_cone_angle.cos()).max(0.001);
+                (2, scale, -outer_cone_angle.cos() * scale)
+            }
+        };
+
+        let mut bytes = position.extend(self.range.unwrap_or(0.0)).bytes();
+        bytes.extend(direction.extend(f32::from_bits(kind)).bytes());
+        bytes.extend(self.color.extend(self.intensity).bytes());
+        let shadow = shadow.map_or(-1, |layer| layer as i32);
+        bytes.extend(
+            Vec3::new(spot_scale, spot_offset, f32::from_bits(shadow as u32))
+                .extend(0.0)
+                .bytes(),
+        );
+        bytes
+    }
+}
+
+/// All lights of the scene in one storage buffer, bound to group 2 together with their shadow maps
+/// and the image based lighting of the [`Environment`]
+#[derive(Getters)]
+pub struct Lights {
+    /// The lights and the nodes they are attached to
+    #[getset(get = "pub")]
+    lights: Vec<(usize, LightData)>,
+    #[getset(get = "pub")]
+    buffer: wgpu::Buffer,
+    #[getset(get = "pub")]
+    shadows: ShadowMaps,
+    #[getset(get = "pub")]
+    bind_group: BindGroupInfo,
+}
+
+impl Lights {
+    /// Creates the light buffer and the shadow maps of the lights placed by `nodes`, which are
+    /// filled by [`Lights::update`].
+    /// <br>
+    /// Without any lights, the [`LightData::fallback`] light is used if
+    /// [`RenderSettings::fallback_light`](crate::renderer::RenderSettings::fallback_light) is set.
+    pub fn new(
+        config: &Configuration,
+        lights: Vec<(usize, LightData)>,
+        nodes: &[Node],
+        environment: &Environment,
+    ) -> Self {
+        let count = lights.len().max(1) as u64;
+
+        let buffer = config.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Light buffer"),
+            size: count * LIGHT_SIZE,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let placed = placed(config, &lights, nodes);
+        let (directional, local): (Vec<_>, Vec<_>) = placed
+            .iter()
+            .filter(|(light, _)| light.cast_shadows)
+            .partition(|(light, _)| light.kind == LightKind::Directional);
+        let casters = directional.len();
+        if casters > MAX_DIRECTIONAL_SHADOWS {
+            warn!("only {MAX_DIRECTIONAL_SHADOWS} of {casters} directional lights cast shadows");
+        }
+        let shadows = ShadowMaps::new(config, casters.min(MAX_DIRECTIONAL_SHADOWS), local.len());
+
+        let [uniform, shadow_maps, sampler, local_shadows, atlas] = shadows.entries();
+        let [environment, specular, irradiance, brdf_lut, environment_sampler] =
+            environment.entries();
+        let bind_group = create_bindgroup(
+            config,
+            &[
+                BindGroupEntryInfo::new(
+                    wgpu::ShaderStages::FRAGMENT,
+                    buffer.as_entire_buffer_binding(),
+                )
+                .storage(true),
+                uniform,
+                shadow_maps,
+                sampler,
+                local_shadows,
+                atlas,
+                environment,
+                specular,
+                irradiance,
+                brdf_lut,
+                environment_sampler,
+            ],
+            "Lights",
+        );
+
+        Self {
+            lights,
+            buffer,
+            shadows,
+            bind_group,
+        }
+    }
+
+    /// Uploads all lights placed at the current world transforms of their nodes and renews their
+    /// shadows for the view of `camera`, `bounds` being a sphere around all casters.
+    /// <br>
+    /// Point and spot lights get their atlas tiles by distance to the camera, which is why this
+    /// runs every frame.
+    pub fn update(
+        &mut self,
+        config: &Configuration,
+        camera: &Camera,
+        nodes: &[Node],
+        bounds: (Vec3, f32),
+    ) {
+        let settings = &config.settings.shadows;
+        let placed = placed(config, &self.lights, nodes);
+        let mut shadows = vec![None; placed.len()];
+
+        let mut directions = Vec::new();
+        for (i, (light, world)) in placed.iter().enumerate() {
+            let directional = light.kind == LightKind::Directional;
+            if light.cast_shadows && directional && directions.len() < self.shadows.casters() {
+                shadows[i] = Some(directions.len() * settings.cascade_count() as usize);
+                directions.push(direction(world));
+            }
+        }
+
+        let camera_position = camera.data().transform.position;
+        let mut candidates: Vec<_> = placed
+            .iter()
+            .enumerate()
+            .filter(|(_, (light, _))| light.cast_shadows)
+            .filter_map(|(i, (light, world))| {
+                let caster = light.local_caster(world, settings.max_distance)?;
+                let distance = (world.transform_point(Vec3::default()) - camera_position).length();
+                Some((i, caster, distance))
+            })
+            .collect();
+        candidates.sort_by(|a, b| a.2.total_cmp(&b.2));
+
+        let mut locals = Vec::new();
+        let mut tiles = 0;
+        for (i, caster) in candidates.into_iter().map(|(i, caster, _)| (i, caster)) {
+            // lights which don't fit into the budget anymore stay unshadowed this frame
+            if tiles + caster.tile_count() <= self.shadows.tile_count() {
+                shadows[i] = Some(tiles);
+                tiles += caster.tile_count();
+                locals.push(caster);
+            }
+        }
+
+        let bytes: Vec<u8> = placed
+            .iter()
+            .zip(shadows)
+            .flat_map(|((light, world), shadow)| light.bytes(world, shadow))
+            .collect();
+        config.queue.write_buffer(&self.buffer, 0, &bytes);
+
+        self.shadows
+            .update(config, camera, &directions, &locals, bounds);
+    }
+}
+
+/// The world space direction a light placed at `world` shines in, its -z axis
+fn direction(world: &Mat4) -> Vec3 {
+    world
+        .transform_vector(Vec3::new(0.0, 0.0, -1.0))
+        .normalize()
+}
+
+/// The lights with the world transforms of their nodes, or the fallback light if there are none
+/// and it is enabled
+fn placed(
+    config: &Configuration,
+    lights: &[(usize, LightData)],
+    nodes: &[Node],
+) -> Vec<(LightData, Mat4)> {
+    if lights.is_empty() && config.settings.fallback_light {
+        return vec![(LightData::fallback(), LightData::fallback_world())];
+    }
+
+    lights
+        .iter()
+        .map(|(node, light)| (light.clone(), nodes[*node].world()))
+        .collect()
+}
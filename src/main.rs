@@ -1,5 +1,13 @@
+use std::process::ExitCode;
+
 use render::run;
 
-fn main() {
-    run();
+fn main() -> ExitCode {
+    match run() {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("error: {err}");
+            ExitCode::FAILURE
+        }
+    }
 }
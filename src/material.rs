@@ -0,0 +1,210 @@
+use getset::Getters;
+use gltf::material::AlphaMode;
+
+use crate::{
+    bindgroup::{create_bindgroup, BindGroupEntryInfo, BindGroupInfo},
+    math::vec::{Vec3, Vec4},
+    renderer::Configuration,
+    texture::{ColorSpace, Textures},
+    uniform_buffer::create_uniform_buffer,
+};
+
+/// A texture used by a material, together with the texture coordinate set it is sampled with
+#[derive(Clone, Copy, Debug)]
+pub struct TextureRef {
+    /// Index of the glTF texture
+    pub texture: usize,
+    /// The `n` of the `TEXCOORD_n` attribute
+    pub tex_coord: u32,
+}
+
+impl From<gltf::texture::Info<'_>> for TextureRef {
+    fn from(info: gltf::texture::Info) -> Self {
+        Self {
+            texture: info.texture().index(),
+            tex_coord: info.tex_coord(),
+        }
+    }
+}
+
+/// The metallic-roughness material model of glTF
+#[derive(Clone, Debug)]
+pub struct MaterialData {
+    pub base_color_factor: Vec4,
+    pub base_color_texture: Option<TextureRef>,
+    pub metallic_factor: f32,
+    pub roughness_factor: f32,
+    /// Metalness is sampled from the blue, roughness from the green channel
+    pub metallic_roughness_texture: Option<TextureRef>,
+    pub normal_scale: f32,
+    pub normal_texture: Option<TextureRef>,
+    pub occlusion_strength: f32,
+    /// Occlusion is sampled from the red channel
+    pub occlusion_texture: Option<TextureRef>,
+    pub emissive_factor: Vec3,
+    pub emissive_texture: Option<TextureRef>,
+    /// How the alpha of the base color is interpreted
+    pub alpha_mode: AlphaMode,
+    /// Fragments with a lower alpha are discarded in `Mask` mode
+    pub alpha_cutoff: f32,
+    /// Back faces are rendered too, with flipped normals
+    pub double_sided: bool,
+}
+
+/// The default material of glTF, used by primitives without a material
+impl Default for MaterialData {
+    fn default() -> Self {
+        Self {
+            base_color_factor: Vec4::one(),
+            base_color_texture: None,
+            metallic_factor: 1.0,
+            roughness_factor: 1.0,
+            metallic_roughness_texture: None,
+            normal_scale: 1.0,
+            normal_texture: None,
+            occlusion_strength: 1.0,
+            occlusion_texture: None,
+            emissive_factor: Vec3::default(),
+            emissive_texture: None,
+            alpha_mode: AlphaMode::Opaque,
+            alpha_cutoff: 0.5,
+            double_sided: false,
+        }
+    }
+}
+
+impl From<&gltf::Material<'_>> for MaterialData {
+    fn from(material: &gltf::Material) -> Self {
+        let pbr = material.pbr_metallic_roughness();
+        let normal = material.normal_texture();
+        let occlusion = material.occlusion_texture();
+
+        Self {
+            base_color_factor: pbr.base_color_factor().into(),
+            base_color_texture: pbr.base_color_texture().map(Into::into),
+            metallic_factor: pbr.metallic_factor(),
+            roughness_factor: pbr.roughness_factor(),
+            metallic_roughness_texture: pbr.metallic_roughness_texture().map(Into::into),
+            normal_scale: normal.as_ref().map_or(1.0, |n| n.scale()),
+            normal_texture: normal.map(|n| TextureRef {
+                texture: n.texture().index(),
+                tex_coord: n.tex_coord(),
+            }),
+            occlusion_strength: occlusion.as_ref().map_or(1.0, |o| o.strength()),
+            occlusion_texture: occlusion.map(|o| TextureRef {
+                texture: o.texture().index(),
+                tex_coord: o.tex_coord(),
+            }),
+            emissive_factor: material.emissive_factor().into(),
+            emissive_texture: material.emissive_texture().map(Into::into),
+            alpha_mode: material.alpha_mode(),
+            alpha_cutoff: material.alpha_cutoff().unwrap_or(0.5),
+            double_sided: material.double_sided(),
+        }
+    }
+}
+
+impl MaterialData {
+    /// The factors, texture coordinate sets and alpha mode as seen by the shaders, matching
+    /// `MaterialUniform` in `pbr.wgsl`
+    pub fn bytes(&self) -> Vec<u8> {
+        let mut bytes = self.base_color_factor.bytes();
+        // a vec3 followed by a scalar is packed into 16 bytes
+        bytes.extend(self.emissive_factor.extend(self.metallic_factor).bytes());
+        bytes.extend(
+            Vec4::new(
+                self.roughness_factor,
+                self.normal_scale,
+                self.occlusion_strength,
+                self.alpha_cutoff,
+            )
+            .bytes(),
+        );
+
+        let tex_coord = |texture: Option<TextureRef>| texture.map_or(0, |t| t.tex_coord);
+        let tex_coords = [
+            tex_coord(self.base_color_texture),
+            tex_coord(self.metallic_roughness_texture),
+            tex_coord(self.normal_texture),
+            tex_coord(self.occlusion_texture),
+            tex_coord(self.emissive_texture),
+            alpha_mode_index(self.alpha_mode),
+            self.normal_texture.is_some() as u32,
+            0,
+        ];
+        bytes.extend(tex_coords.iter().flat_map(|t| t.to_le_bytes()));
+        bytes
+    }
+}
+
+/// The value of `MaterialUniform::alpha_mode` in `pbr.wgsl`
+fn alpha_mode_index(mode: AlphaMode) -> u32 {
+    match mode {
+        AlphaMode::Opaque => 0,
+        AlphaMode::Mask => 1,
+        AlphaMode::Blend => 2,
+    }
+}
+
+#[derive(Getters)]
+pub struct Material {
+    #[getset(get = "pub")]
+    data: MaterialData,
+    #[getset(get = "pub")]
+    buffer: wgpu::Buffer,
+    #[getset(get = "pub")]
+    bind_group: BindGroupInfo,
+}
+
+impl Material {
+    pub fn new(
+        config: &Configuration,
+        data: MaterialData,
+        textures: &Textures,
+        label: &str,
+    ) -> Self {
+        let buffer = create_uniform_buffer(
+            config,
+            &format!("Material buffer for '{}'", label),
+            &data.bytes(),
+        );
+
+        // ordered like the bindings of `pbr.wgsl`
+        let texture_bindings = [
+            (data.base_color_texture, ColorSpace::Srgb, textures.white()),
+            (
+                data.metallic_roughness_texture,
+                ColorSpace::Linear,
+                textures.white(),
+            ),
+            (
+                data.normal_texture,
+                ColorSpace::Linear,
+                textures.flat_normal(),
+            ),
+            (data.occlusion_texture, ColorSpace::Linear, textures.white()),
+            (data.emissive_texture, ColorSpace::Srgb, textures.white()),
+        ]
+        .map(|(texture, color_space, fallback)| textures.binding(texture, color_space, fallback));
+
+        let mut entries = vec![BindGroupEntryInfo::new(
+            wgpu::ShaderStages::FRAGMENT,
+            buffer.as_entire_buffer_binding(),
+        )];
+        for (view, sampler) in texture_bindings {
+            entries.push(BindGroupEntryInfo::new(wgpu::ShaderStages::FRAGMENT, view));
+            entries.push(BindGroupEntryInfo::new(
+                wgpu::ShaderStages::FRAGMENT,
+                sampler,
+            ));
+        }
+
+        let bind_group = create_bindgroup(config, &entries, &format!("Material '{}'", label));
+
+        Self {
+            data,
+            buffer,
+            bind_group,
+        }
+    }
+}
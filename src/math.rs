@@ -1,3 +1,4 @@
 pub mod mat;
+pub mod quat;
 pub mod transform;
 pub mod vec;
@@ -1,6 +1,13 @@
-use super::{transform::Transform, vec::Vec4};
+use std::ops::Mul;
 
-#[derive(Clone, Copy, Debug)]
+use super::{
+    quat::Quat,
+    transform::Transform,
+    vec::{Vec3, Vec4},
+};
+
+/// A column major 4x4 matrix, `x`, `y`, `z` and `w` are the columns
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub struct Mat4 {
     pub x: Vec4,
     pub y: Vec4,
@@ -44,9 +51,27 @@ impl Mat4 {
         Self { x, y, z, w }
     }
 
-    /// rotating a matrix, according to Tait-Bryan angles in Z -> Y -> X order in radiants
-    /// <br>
-    /// Reference: https://en.wikipedia.org/wiki/Euler_angles#Rotation_matrix
+    pub fn identity() -> Self {
+        Self::default()
+    }
+
+    pub fn from_translation(t: Vec3) -> Self {
+        Self {
+            w: t.extend(1.0),
+            ..Default::default()
+        }
+    }
+
+    pub fn from_scale(s: Vec3) -> Self {
+        Self::new(
+            Vec4::new(s.x, 0.0, 0.0, 0.0),
+            Vec4::new(0.0, s.y, 0.0, 0.0),
+            Vec4::new(0.0, 0.0, s.z, 0.0),
+            Vec4::new(0.0, 0.0, 0.0, 1.0),
+        )
+    }
+
+    /// The matrix of a transform, applying scale, then rotation, then translation
     pub fn transform(t: &Transform) -> Self {
         let Transform {
             position,
@@ -54,50 +79,142 @@ impl Mat4 {
             scale,
         } = t;
 
-        let yaw = rotation.x;
-        let pitch = rotation.y;
-        let roll = rotation.z;
+        let rotation = rotation.to_mat4();
 
-        let cos_yaw = yaw.cos();
-        let sin_yaw = yaw.sin();
-        let cos_pitch = pitch.cos();
-        let sin_pitch = pitch.sin();
-        let cos_roll = roll.cos();
-        let sin_roll = roll.sin();
+        Self::new(
+            rotation.x * scale.x,
+            rotation.y * scale.y,
+            rotation.z * scale.z,
+            position.extend(1.0),
+        )
+    }
 
-        Self {
-            x: Vec4 {
-                x: cos_yaw * cos_pitch * scale.x,
-                y: (cos_yaw * sin_pitch * sin_roll - sin_yaw * cos_roll) * scale.y,
-                z: (cos_yaw * sin_pitch * cos_roll + sin_yaw * sin_roll) * scale.z,
-                w: 0.0,
-            },
-            y: Vec4 {
-                x: sin_yaw * cos_pitch * scale.x,
-                y: (sin_yaw * sin_pitch * sin_roll + cos_yaw * cos_roll) * scale.y,
-                z: (sin_yaw * sin_pitch * cos_roll - cos_yaw * sin_roll) * scale.z,
-                w: 0.0,
-            },
-            z: Vec4 {
-                x: -sin_pitch * scale.x,
-                y: (cos_pitch * sin_roll) * scale.y,
-                z: (cos_pitch * cos_roll) * scale.z,
-                w: 0.0,
-            },
-            w: Vec4 {
-                x: position.x,
-                y: position.y,
-                z: position.z,
-                w: 1.0,
-            },
+    /// A right handed view matrix, looking from `eye` towards `target`
+    pub fn look_at(eye: Vec3, target: Vec3, up: Vec3) -> Self {
+        let forward = (target - eye).normalize();
+        let right = forward.cross(up).normalize();
+        let up = right.cross(forward);
+
+        Self::new(
+            Vec4::new(right.x, up.x, -forward.x, 0.0),
+            Vec4::new(right.y, up.y, -forward.y, 0.0),
+            Vec4::new(right.z, up.z, -forward.z, 0.0),
+            Vec4::new(-right.dot(eye), -up.dot(eye), forward.dot(eye), 1.0),
+        )
+    }
+
+    /// A right handed perspective projection, mapping depth to `0..1` as wgpu expects
+    /// <br>
+    /// `fov` is the field of view on the Y-Axis in radiants
+    pub fn perspective(fov: f32, aspect: f32, near: f32, far: f32) -> Self {
+        let y = 1.0 / (fov / 2.0).tan();
+
+        Self::new(
+            Vec4::new(y / aspect, 0.0, 0.0, 0.0),
+            Vec4::new(0.0, y, 0.0, 0.0),
+            Vec4::new(0.0, 0.0, far / (near - far), -1.0),
+            Vec4::new(0.0, 0.0, (far * near) / (near - far), 0.0),
+        )
+    }
+
+    /// A right handed orthographic projection, mapping depth to `0..1` as wgpu expects
+    /// <br>
+    /// `xmag` and `ymag` are half the width and height of the view volume
+    pub fn orthographic(xmag: f32, ymag: f32, near: f32, far: f32) -> Self {
+        Self::new(
+            Vec4::new(1.0 / xmag, 0.0, 0.0, 0.0),
+            Vec4::new(0.0, 1.0 / ymag, 0.0, 0.0),
+            Vec4::new(0.0, 0.0, 1.0 / (near - far), 0.0),
+            Vec4::new(0.0, 0.0, near / (near - far), 1.0),
+        )
+    }
+
+    pub fn transpose(self) -> Self {
+        Self::new(
+            Vec4::new(self.x.x, self.y.x, self.z.x, self.w.x),
+            Vec4::new(self.x.y, self.y.y, self.z.y, self.w.y),
+            Vec4::new(self.x.z, self.y.z, self.z.z, self.w.z),
+            Vec4::new(self.x.w, self.y.w, self.z.w, self.w.w),
+        )
+    }
+
+    pub fn determinant(&self) -> f32 {
+        let m = self.to_array();
+        let inv = cofactors(&m);
+
+        m[0] * inv[0] + m[1] * inv[4] + m[2] * inv[8] + m[3] * inv[12]
+    }
+
+    /// Returns `None` if the matrix is singular
+    /// <br>
+    /// Reference: https://stackoverflow.com/a/1148405
+    pub fn inverse(&self) -> Option<Self> {
+        let m = self.to_array();
+        let inv = cofactors(&m);
+
+        let det = m[0] * inv[0] + m[1] * inv[4] + m[2] * inv[8] + m[3] * inv[12];
+        if det == 0.0 {
+            return None;
         }
+
+        Some(Self::from_array(inv.map(|v| v / det)))
     }
 
-    pub fn bytes(self) -> Vec<u8> {
-        let floats = [
+    /// Splits an affine matrix into translation, rotation and scale, assuming no shear
+    pub fn decompose(&self) -> Transform {
+        let scale = Vec3::new(
+            self.x.truncate().length(),
+            self.y.truncate().length(),
+            self.z.truncate().length(),
+        );
+
+        // a negative determinant means that one axis is mirrored
+        let scale = if self.determinant() < 0.0 {
+            Vec3::new(-scale.x, scale.y, scale.z)
+        } else {
+            scale
+        };
+
+        let rotation = Self::new(
+            self.x / scale.x,
+            self.y / scale.y,
+            self.z / scale.z,
+            Vec4::new(0.0, 0.0, 0.0, 1.0),
+        );
+
+        Transform::new(self.w.truncate(), Quat::from_mat4(&rotation), scale)
+    }
+
+    /// Transforms a point, including the translation
+    pub fn transform_point(&self, p: Vec3) -> Vec3 {
+        let p = *self * p.extend(1.0);
+        p.truncate() / p.w
+    }
+
+    /// Transforms a direction, ignoring the translation
+    pub fn transform_vector(&self, v: Vec3) -> Vec3 {
+        (*self * v.extend(0.0)).truncate()
+    }
+
+    /// The columns flattened into an array
+    pub fn to_array(&self) -> [f32; 16] {
+        [
             self.x.x, self.x.y, self.x.z, self.x.w, self.y.x, self.y.y, self.y.z, self.y.w,
             self.z.x, self.z.y, self.z.z, self.z.w, self.w.x, self.w.y, self.w.z, self.w.w,
-        ];
+        ]
+    }
+
+    pub fn from_array(m: [f32; 16]) -> Self {
+        Self::new(
+            Vec4::new(m[0], m[1], m[2], m[3]),
+            Vec4::new(m[4], m[5], m[6], m[7]),
+            Vec4::new(m[8], m[9], m[10], m[11]),
+            Vec4::new(m[12], m[13], m[14], m[15]),
+        )
+    }
+
+    pub fn bytes(self) -> Vec<u8> {
+        let floats = self.to_array();
 
         /// Resource: https://users.rust-lang.org/t/vec-f32-to-u8/21522/10
         fn vf_to_u8(v: &[f32]) -> &[u8] {
@@ -107,3 +224,169 @@ impl Mat4 {
         vf_to_u8(&floats).to_vec()
     }
 }
+
+/// The transposed cofactor matrix (adjugate) of a flattened 4x4 matrix
+fn cofactors(m: &[f32; 16]) -> [f32; 16] {
+    let mut inv = [0.0; 16];
+
+    inv[0] = m[5] * m[10] * m[15] - m[5] * m[11] * m[14] - m[9] * m[6] * m[15]
+        + m[9] * m[7] * m[14]
+        + m[13] * m[6] * m[11]
+        - m[13] * m[7] * m[10];
+    inv[4] = -m[4] * m[10] * m[15] + m[4] * m[11] * m[14] + m[8] * m[6] * m[15]
+        - m[8] * m[7] * m[14]
+        - m[12] * m[6] * m[11]
+        + m[12] * m[7] * m[10];
+    inv[8] = m[4] * m[9] * m[15] - m[4] * m[11] * m[13] - m[8] * m[5] * m[15]
+        + m[8] * m[7] * m[13]
+        + m[12] * m[5] * m[11]
+        - m[12] * m[7] * m[9];
+    inv[12] = -m[4] * m[9] * m[14] + m[4] * m[10] * m[13] + m[8] * m[5] * m[14]
+        - m[8] * m[6] * m[13]
+        - m[12] * m[5] * m[10]
+        + m[12] * m[6] * m[9];
+    inv[1] = -m[1] * m[10] * m[15] + m[1] * m[11] * m[14] + m[9] * m[2] * m[15]
+        - m[9] * m[3] * m[14]
+        - m[13] * m[2] * m[11]
+        + m[13] * m[3] * m[10];
+    inv[5] = m[0] * m[10] * m[15] - m[0] * m[11] * m[14] - m[8] * m[2] * m[15]
+        + m[8] * m[3] * m[14]
+        + m[12] * m[2] * m[11]
+        - m[12] * m[3] * m[10];
+    inv[9] = -m[0] * m[9] * m[15] + m[0] * m[11] * m[13] + m[8] * m[1] * m[15]
+        - m[8] * m[3] * m[13]
+        - m[12] * m[1] * m[11]
+        + m[12] * m[3] * m[9];
+    inv[13] = m[0] * m[9] * m[14] - m[0] * m[10] * m[13] - m[8] * m[1] * m[14]
+        + m[8] * m[2] * m[13]
+        + m[12] * m[1] * m[10]
+        - m[12] * m[2] * m[9];
+    inv[2] = m[1] * m[6] * m[15] - m[1] * m[7] * m[14] - m[5] * m[2] * m[15]
+        + m[5] * m[3] * m[14]
+        + m[13] * m[2] * m[7]
+        - m[13] * m[3] * m[6];
+    inv[6] = -m[0] * m[6] * m[15] + m[0] * m[7] * m[14] + m[4] * m[2] * m[15]
+        - m[4] * m[3] * m[14]
+        - m[12] * m[2] * m[7]
+        + m[12] * m[3] * m[6];
+    inv[10] = m[0] * m[5] * m[15] - m[0] * m[7] * m[13] - m[4] * m[1] * m[15]
+        + m[4] * m[3] * m[13]
+        + m[12] * m[1] * m[7]
+        - m[12] * m[3] * m[5];
+    inv[14] = -m[0] * m[5] * m[14] + m[0] * m[6] * m[13] + m[4] * m[1] * m[14]
+        - m[4] * m[2] * m[13]
+        - m[12] * m[1] * m[6]
+        + m[12] * m[2] * m[5];
+    inv[3] = -m[1] * m[6] * m[11] + m[1] * m[7] * m[10] + m[5] * m[2] * m[11]
+        - m[5] * m[3] * m[10]
+        - m[9] * m[2] * m[7]
+        + m[9] * m[3] * m[6];
+    inv[7] = m[0] * m[6] * m[11] - m[0] * m[7] * m[10] - m[4] * m[2] * m[11]
+        + m[4] * m[3] * m[10]
+        + m[8] * m[2] * m[7]
+        - m[8] * m[3] * m[6];
+    inv[11] = -m[0] * m[5] * m[11] + m[0] * m[7] * m[9] + m[4] * m[1] * m[11]
+        - m[4] * m[3] * m[9]
+        - m[8] * m[1] * m[7]
+        + m[8] * m[3] * m[5];
+    inv[15] = m[0] * m[5] * m[10] - m[0] * m[6] * m[9] - m[4] * m[1] * m[10]
+        + m[4] * m[2] * m[9]
+        + m[8] * m[1] * m[6]
+        - m[8] * m[2] * m[5];
+
+    inv
+}
+
+impl Mul<Vec4> for Mat4 {
+    type Output = Vec4;
+
+    fn mul(self, v: Vec4) -> Vec4 {
+        self.x * v.x + self.y * v.y + self.z * v.z + self.w * v.w
+    }
+}
+
+impl Mul for Mat4 {
+    type Output = Mat4;
+
+    fn mul(self, rhs: Mat4) -> Mat4 {
+        Mat4::new(self * rhs.x, self * rhs.y, self * rhs.z, self * rhs.w)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EPSILON: f32 = 1e-4;
+
+    fn assert_mat_eq(a: Mat4, b: Mat4) {
+        let close = a
+            .to_array()
+            .iter()
+            .zip(b.to_array())
+            .all(|(a, b)| (a - b).abs() < EPSILON);
+        assert!(close, "{a:?} != {b:?}");
+    }
+
+    fn affine() -> Mat4 {
+        Mat4::transform(&Transform::new(
+            Vec3::new(1.0, -2.0, 3.5),
+            Quat::from_axis_angle(Vec3::new(1.0, 2.0, 3.0), 0.7),
+            Vec3::new(2.0, 0.5, 3.0),
+        ))
+    }
+
+    #[test]
+    fn inverse_times_matrix_is_identity() {
+        let projected = Mat4::perspective(1.0, 1.5, 0.1, 100.0) * affine();
+
+        for m in [affine(), projected] {
+            let inverse = m.inverse().expect("matrix is invertible");
+            assert_mat_eq(inverse * m, Mat4::identity());
+            assert_mat_eq(m * inverse, Mat4::identity());
+        }
+    }
+
+    #[test]
+    fn singular_matrix_has_no_inverse() {
+        assert_eq!(Mat4::from_scale(Vec3::new(1.0, 0.0, 1.0)).inverse(), None);
+    }
+
+    #[test]
+    fn decompose_compose_round_trip() {
+        let mirrored = affine() * Mat4::from_scale(Vec3::new(1.0, -1.0, 1.0));
+
+        for m in [Mat4::identity(), affine(), mirrored] {
+            assert_mat_eq(Mat4::transform(&m.decompose()), m);
+        }
+    }
+
+    #[test]
+    fn decompose_keeps_translation_and_scale() {
+        let transform = affine().decompose();
+
+        assert!((transform.position - Vec3::new(1.0, -2.0, 3.5)).length() < EPSILON);
+        assert!((transform.scale - Vec3::new(2.0, 0.5, 3.0)).length() < EPSILON);
+    }
+
+    #[test]
+    fn perspective_maps_near_and_far_to_0_and_1() {
+        let (near, far) = (0.1, 100.0);
+        let m = Mat4::perspective(1.0, 1.5, near, far);
+
+        assert!(m.transform_point(Vec3::new(0.0, 0.0, -near)).z.abs() < EPSILON);
+        assert!((m.transform_point(Vec3::new(0.0, 0.0, -far)).z - 1.0).abs() < EPSILON);
+    }
+
+    #[test]
+    fn orthographic_maps_near_and_far_to_0_and_1() {
+        let (near, far) = (0.5, 20.0);
+        let m = Mat4::orthographic(4.0, 3.0, near, far);
+
+        assert!(m.transform_point(Vec3::new(0.0, 0.0, -near)).z.abs() < EPSILON);
+        assert!((m.transform_point(Vec3::new(0.0, 0.0, -far)).z - 1.0).abs() < EPSILON);
+
+        let corner = m.transform_point(Vec3::new(4.0, 3.0, -near));
+        assert!((corner.x - 1.0).abs() < EPSILON && (corner.y - 1.0).abs() < EPSILON);
+    }
+}
@@ -0,0 +1,261 @@
+use std::ops::{Mul, Neg};
+
+use super::{
+    mat::Mat4,
+    vec::{Vec3, Vec4},
+};
+
+/// A rotation quaternion, `w` is the real part
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Quat {
+    pub x: f32,
+    pub y: f32,
+    pub z: f32,
+    pub w: f32,
+}
+
+impl Default for Quat {
+    fn default() -> Self {
+        Self::identity()
+    }
+}
+
+/// Raw quaternion data in `[x, y, z, w]` order, as stored in a glTF
+impl From<[f32; 4]> for Quat {
+    fn from(q: [f32; 4]) -> Self {
+        Quat::new(q[0], q[1], q[2], q[3])
+    }
+}
+
+impl Quat {
+    pub fn new(x: f32, y: f32, z: f32, w: f32) -> Self {
+        Self { x, y, z, w }
+    }
+
+    pub fn identity() -> Self {
+        Self::new(0.0, 0.0, 0.0, 1.0)
+    }
+
+    /// Rotation of `angle` radians counter clockwise around `axis`, which doesn't need to be normalized
+    pub fn from_axis_angle(axis: Vec3, angle: f32) -> Self {
+        let axis = axis.normalize();
+        let (sin, cos) = (angle / 2.0).sin_cos();
+
+        Self::new(axis.x * sin, axis.y * sin, axis.z * sin, cos)
+    }
+
+    /// Extracts the rotation of the upper 3x3 part of a matrix, which must not contain any scale
+    /// <br>
+    /// Reference: https://www.euclideanspace.com/maths/geometry/rotations/conversions/matrixToQuaternion/
+    pub fn from_mat4(m: &Mat4) -> Self {
+        // m.<column>.<row>
+        let trace = m.x.x + m.y.y + m.z.z;
+
+        let q = if trace > 0.0 {
+            let s = (trace + 1.0).sqrt() * 2.0;
+            Self::new(
+                (m.y.z - m.z.y) / s,
+                (m.z.x - m.x.z) / s,
+                (m.x.y - m.y.x) / s,
+                0.25 * s,
+            )
+        } else if m.x.x > m.y.y && m.x.x > m.z.z {
+            let s = (1.0 + m.x.x - m.y.y - m.z.z).sqrt() * 2.0;
+            Self::new(
+                0.25 * s,
+                (m.y.x + m.x.y) / s,
+                (m.z.x + m.x.z) / s,
+                (m.y.z - m.z.y) / s,
+            )
+        } else if m.y.y > m.z.z {
+            let s = (1.0 + m.y.y - m.x.x - m.z.z).sqrt() * 2.0;
+            Self::new(
+                (m.y.x + m.x.y) / s,
+                0.25 * s,
+                (m.z.y + m.y.z) / s,
+                (m.z.x - m.x.z) / s,
+            )
+        } else {
+            let s = (1.0 + m.z.z - m.x.x - m.y.y).sqrt() * 2.0;
+            Self::new(
+                (m.z.x + m.x.z) / s,
+                (m.z.y + m.y.z) / s,
+                0.25 * s,
+                (m.x.y - m.y.x) / s,
+            )
+        };
+
+        q.normalize()
+    }
+
+    /// The rotation as a homogeneous matrix
+    pub fn to_mat4(self) -> Mat4 {
+        let Quat { x, y, z, w } = self;
+
+        let (xx, yy, zz) = (x * x, y * y, z * z);
+        let (xy, xz, yz) = (x * y, x * z, y * z);
+        let (wx, wy, wz) = (w * x, w * y, w * z);
+
+        Mat4::new(
+            Vec4::new(1.0 - 2.0 * (yy + zz), 2.0 * (xy + wz), 2.0 * (xz - wy), 0.0),
+            Vec4::new(2.0 * (xy - wz), 1.0 - 2.0 * (xx + zz), 2.0 * (yz + wx), 0.0),
+            Vec4::new(2.0 * (xz + wy), 2.0 * (yz - wx), 1.0 - 2.0 * (xx + yy), 0.0),
+            Vec4::new(0.0, 0.0, 0.0, 1.0),
+        )
+    }
+
+    pub fn dot(self, rhs: Quat) -> f32 {
+        self.x * rhs.x + self.y * rhs.y + self.z * rhs.z + self.w * rhs.w
+    }
+
+    pub fn length(self) -> f32 {
+        self.dot(self).sqrt()
+    }
+
+    pub fn normalize(self) -> Quat {
+        let length = self.length();
+        Self::new(
+            self.x / length,
+            self.y / length,
+            self.z / length,
+            self.w / length,
+        )
+    }
+
+    /// The inverse rotation, assuming a unit quaternion
+    pub fn conjugate(self) -> Quat {
+        Self::new(-self.x, -self.y, -self.z, self.w)
+    }
+
+    /// Spherical linear interpolation along the shortest path
+    /// <br>
+    /// Reference: https://en.wikipedia.org/wiki/Slerp#Source_code
+    pub fn slerp(self, other: Quat, t: f32) -> Quat {
+        let mut dot = self.dot(other);
+
+        // q and -q are the same rotation, take the shorter way around
+        let other = if dot < 0.0 {
+            dot = -dot;
+            -other
+        } else {
+            other
+        };
+
+        // nearly parallel, so fall back to a normalized lerp to avoid dividing by ~0
+        if dot > 0.9995 {
+            return Self::new(
+                self.x + (other.x - self.x) * t,
+                self.y + (other.y - self.y) * t,
+                self.z + (other.z - self.z) * t,
+                self.w + (other.w - self.w) * t,
+            )
+            .normalize();
+        }
+
+        let theta_0 = dot.acos();
+        let theta = theta_0 * t;
+        let sin_theta_0 = theta_0.sin();
+
+        let s0 = (theta_0 - theta).sin() / sin_theta_0;
+        let s1 = theta.sin() / sin_theta_0;
+
+        Self::new(
+            self.x * s0 + other.x * s1,
+            self.y * s0 + other.y * s1,
+            self.z * s0 + other.z * s1,
+            self.w * s0 + other.w * s1,
+        )
+    }
+}
+
+/// Combines two rotations, `a * b` first rotates by `b`, then by `a`
+impl Mul for Quat {
+    type Output = Quat;
+
+    fn mul(self, rhs: Quat) -> Quat {
+        Quat::new(
+            self.w * rhs.x + self.x * rhs.w + self.y * rhs.z - self.z * rhs.y,
+            self.w * rhs.y - self.x * rhs.z + self.y * rhs.w + self.z * rhs.x,
+            self.w * rhs.z + self.x * rhs.y - self.y * rhs.x + self.z * rhs.w,
+            self.w * rhs.w - self.x * rhs.x - self.y * rhs.y - self.z * rhs.z,
+        )
+    }
+}
+
+/// Rotates a vector
+impl Mul<Vec3> for Quat {
+    type Output = Vec3;
+
+    fn mul(self, v: Vec3) -> Vec3 {
+        let u = Vec3::new(self.x, self.y, self.z);
+        let t = u.cross(v) * 2.0;
+
+        v + t * self.w + u.cross(t)
+    }
+}
+
+impl Neg for Quat {
+    type Output = Quat;
+
+    fn neg(self) -> Quat {
+        Quat::new(-self.x, -self.y, -self.z, -self.w)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EPSILON: f32 = 1e-4;
+
+    /// Covers every branch of [`Quat::from_mat4`], the last three rotate by almost half a turn
+    fn rotations() -> [Quat; 5] {
+        [
+            Quat::identity(),
+            Quat::from_axis_angle(Vec3::new(1.0, 2.0, 3.0), 0.7),
+            Quat::from_axis_angle(Vec3::new(1.0, 0.1, 0.0), 3.0),
+            Quat::from_axis_angle(Vec3::new(0.1, 1.0, 0.0), 3.0),
+            Quat::from_axis_angle(Vec3::new(0.0, 0.1, 1.0), 3.0),
+        ]
+    }
+
+    fn assert_vec_eq(a: Vec3, b: Vec3) {
+        assert!((a - b).length() < EPSILON, "{a:?} != {b:?}");
+    }
+
+    #[test]
+    fn quat_mat_quat_round_trip() {
+        for q in rotations() {
+            let round_trip = Quat::from_mat4(&q.to_mat4());
+            // q and -q are the same rotation
+            assert!(
+                (q.dot(round_trip).abs() - 1.0).abs() < EPSILON,
+                "{q:?} != {round_trip:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn rotating_a_vector_matches_the_matrix() {
+        let v = Vec3::new(0.3, -1.2, 2.0);
+
+        for q in rotations() {
+            assert_vec_eq(q * v, q.to_mat4().transform_vector(v));
+        }
+    }
+
+    #[test]
+    fn axis_angle_rotates_counter_clockwise() {
+        let q = Quat::from_axis_angle(Vec3::new(0.0, 0.0, 2.0), std::f32::consts::FRAC_PI_2);
+
+        assert_vec_eq(q * Vec3::new(1.0, 0.0, 0.0), Vec3::new(0.0, 1.0, 0.0));
+    }
+
+    #[test]
+    fn product_applies_right_rotation_first() {
+        let [_, a, b, ..] = rotations();
+        let v = Vec3::new(0.3, -1.2, 2.0);
+
+        assert_vec_eq((a * b) * v, a * (b * v));
+    }
+}
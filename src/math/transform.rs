@@ -1,14 +1,14 @@
-use super::vec::Vec3;
+use super::{quat::Quat, vec::Vec3};
 
 #[derive(Clone, Debug)]
 pub struct Transform {
     pub position: Vec3,
-    pub rotation: Vec3,
+    pub rotation: Quat,
     pub scale: Vec3,
 }
 
 impl Transform {
-    pub fn new(position: Vec3, rotation: Vec3, scale: Vec3) -> Self {
+    pub fn new(position: Vec3, rotation: Quat, scale: Vec3) -> Self {
         Self {
             position,
             rotation,
@@ -26,3 +26,10 @@ impl Default for Transform {
         }
     }
 }
+
+/// Decomposed glTF node transform data
+impl From<([f32; 3], [f32; 4], [f32; 3])> for Transform {
+    fn from((position, rotation, scale): ([f32; 3], [f32; 4], [f32; 3])) -> Self {
+        Self::new(position.into(), rotation.into(), scale.into())
+    }
+}
@@ -1,4 +1,6 @@
-#[derive(Clone, Copy, Debug, Default)]
+use std::ops::{Add, Div, Mul, Neg, Sub};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
 pub struct Vec4 {
     pub x: f32,
     pub y: f32,
@@ -21,23 +23,122 @@ impl Vec4 {
             w: 1.0,
         }
     }
+    /// Drops the `w` component
+    pub fn truncate(self) -> Vec3 {
+        Vec3::new(self.x, self.y, self.z)
+    }
+    pub fn dot(self, rhs: Vec4) -> f32 {
+        self.x * rhs.x + self.y * rhs.y + self.z * rhs.z + self.w * rhs.w
+    }
+    pub fn length(self) -> f32 {
+        self.dot(self).sqrt()
+    }
+    /// Returns the zero vector unchanged
+    pub fn normalize(self) -> Vec4 {
+        let length = self.length();
+        if length == 0.0 {
+            self
+        } else {
+            self / length
+        }
+    }
+    /// Linear interpolation, `t = 0` returns `self`, `t = 1` returns `other`
+    pub fn lerp(self, other: Vec4, t: f32) -> Vec4 {
+        self + (other - self) * t
+    }
+    pub fn bytes(self) -> Vec<u8> {
+        [self.x, self.y, self.z, self.w]
+            .iter()
+            .flat_map(|f| f.to_le_bytes())
+            .collect()
+    }
+}
+
+impl From<[f32; 4]> for Vec4 {
+    fn from(v: [f32; 4]) -> Self {
+        Vec4::new(v[0], v[1], v[2], v[3])
+    }
+}
+
+impl Add for Vec4 {
+    type Output = Vec4;
+
+    fn add(self, rhs: Vec4) -> Vec4 {
+        Vec4::new(
+            self.x + rhs.x,
+            self.y + rhs.y,
+            self.z + rhs.z,
+            self.w + rhs.w,
+        )
+    }
+}
+
+impl Sub for Vec4 {
+    type Output = Vec4;
+
+    fn sub(self, rhs: Vec4) -> Vec4 {
+        Vec4::new(
+            self.x - rhs.x,
+            self.y - rhs.y,
+            self.z - rhs.z,
+            self.w - rhs.w,
+        )
+    }
+}
+
+impl Mul<f32> for Vec4 {
+    type Output = Vec4;
+
+    fn mul(self, rhs: f32) -> Vec4 {
+        Vec4::new(self.x * rhs, self.y * rhs, self.z * rhs, self.w * rhs)
+    }
+}
+
+/// Component-wise multiplication
+impl Mul for Vec4 {
+    type Output = Vec4;
+
+    fn mul(self, rhs: Vec4) -> Vec4 {
+        Vec4::new(
+            self.x * rhs.x,
+            self.y * rhs.y,
+            self.z * rhs.z,
+            self.w * rhs.w,
+        )
+    }
+}
+
+impl Div<f32> for Vec4 {
+    type Output = Vec4;
+
+    fn div(self, rhs: f32) -> Vec4 {
+        Vec4::new(self.x / rhs, self.y / rhs, self.z / rhs, self.w / rhs)
+    }
 }
 
-#[derive(Clone, Copy, Debug, Default)]
+impl Neg for Vec4 {
+    type Output = Vec4;
+
+    fn neg(self) -> Vec4 {
+        Vec4::new(-self.x, -self.y, -self.z, -self.w)
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
 pub struct Vec3 {
     pub x: f32,
     pub y: f32,
     pub z: f32,
 }
 
-impl Into<Vec3> for [f32; 3] {
-    fn into(self) -> Vec3 {
-        Vec3::new(self[0], self[1], -self[2])
+impl From<[f32; 3]> for Vec3 {
+    fn from(v: [f32; 3]) -> Self {
+        Vec3::new(v[0], v[1], v[2])
     }
 }
 
 impl Vec3 {
-    pub fn new(x: f32, y: f32, z: f32) -> Self {
+    pub const fn new(x: f32, y: f32, z: f32) -> Self {
         Self { x, y, z }
     }
     pub fn xyz(x: f32, y: f32, z: f32) -> Self {
@@ -50,38 +151,84 @@ impl Vec3 {
             z: 1.0,
         }
     }
-    /// converting raw quaternion data (as stored in a Gltf to a tait-bryan-angle vector (Z -> Y -> X)) because I'm not smart enough for quaternions.
-    /// <br>
-    /// Reference: http://marc-b-reynolds.github.io/math/2017/04/18/TaitEuler.html#mjx-eqn:cos2_1
-    pub fn euler_from_quaternion_data(q: [f32; 4]) -> Vec3 {
-        let x = -q[2]; // Due to
-        let y = -q[1]; // blenders
-        let z = -q[0]; // flipped
-        let w = q[3]; // coordinates!
-
-        let t0 = (x + z) * (x - z); // x^2 - z^2
-        let t1 = (w + y) * (w - y); // w^2 - y^2
-        let xx = 0.5 * (t0 + t1); // 1/2 x of x'
-        let xy = x * y + w * z; // 1/2 y of x'
-        let xz = w * y - x * z; // 1/2 z of x'
-        let t = xx * xx + xy * xy; // cos(theta)^2
-        let yz = 2.0 * (y * z + w * x); // z of y'
-
-        let mut v = Vec3 {
-            x: 0.0,
-            y: 0.0,
-            z: 0.0,
-        };
-
-        v.z = f32::atan2(xy, xx); // yaw   (psi)
-        v.y = f32::atan2(xz, t.sqrt()); // pitch (theta)
-
-        if t != 0.0 {
-            v.x = f32::atan2(yz, t1 - t0);
+    /// Appends a `w` component
+    pub fn extend(self, w: f32) -> Vec4 {
+        Vec4::new(self.x, self.y, self.z, w)
+    }
+    pub fn dot(self, rhs: Vec3) -> f32 {
+        self.x * rhs.x + self.y * rhs.y + self.z * rhs.z
+    }
+    /// Right handed cross product
+    pub fn cross(self, rhs: Vec3) -> Vec3 {
+        Vec3::new(
+            self.y * rhs.z - self.z * rhs.y,
+            self.z * rhs.x - self.x * rhs.z,
+            self.x * rhs.y - self.y * rhs.x,
+        )
+    }
+    pub fn length(self) -> f32 {
+        self.dot(self).sqrt()
+    }
+    /// Returns the zero vector unchanged
+    pub fn normalize(self) -> Vec3 {
+        let length = self.length();
+        if length == 0.0 {
+            self
         } else {
-            v.x = 2.0 * f32::atan2(x, w) - xz.signum() * v.z;
+            self / length
         }
+    }
+    /// Linear interpolation, `t = 0` returns `self`, `t = 1` returns `other`
+    pub fn lerp(self, other: Vec3, t: f32) -> Vec3 {
+        self + (other - self) * t
+    }
+}
+
+impl Add for Vec3 {
+    type Output = Vec3;
+
+    fn add(self, rhs: Vec3) -> Vec3 {
+        Vec3::new(self.x + rhs.x, self.y + rhs.y, self.z + rhs.z)
+    }
+}
+
+impl Sub for Vec3 {
+    type Output = Vec3;
+
+    fn sub(self, rhs: Vec3) -> Vec3 {
+        Vec3::new(self.x - rhs.x, self.y - rhs.y, self.z - rhs.z)
+    }
+}
+
+impl Mul<f32> for Vec3 {
+    type Output = Vec3;
+
+    fn mul(self, rhs: f32) -> Vec3 {
+        Vec3::new(self.x * rhs, self.y * rhs, self.z * rhs)
+    }
+}
+
+/// Component-wise multiplication
+impl Mul for Vec3 {
+    type Output = Vec3;
+
+    fn mul(self, rhs: Vec3) -> Vec3 {
+        Vec3::new(self.x * rhs.x, self.y * rhs.y, self.z * rhs.z)
+    }
+}
+
+impl Div<f32> for Vec3 {
+    type Output = Vec3;
+
+    fn div(self, rhs: f32) -> Vec3 {
+        Vec3::new(self.x / rhs, self.y / rhs, self.z / rhs)
+    }
+}
+
+impl Neg for Vec3 {
+    type Output = Vec3;
 
-        v
+    fn neg(self) -> Vec3 {
+        Vec3::new(-self.x, -self.y, -self.z)
     }
 }
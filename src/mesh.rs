@@ -1,55 +1,31 @@
+use getset::{CopyGetters, Getters};
+
 use crate::{
-    bindgroup::{self, BindGroupEntryInfo, BindGroupInfo},
-    camera::Camera,
-    math::{mat::Mat4, transform::Transform, vec::Vec3},
-    model_buffer_info::ModelBufferIndo,
-    primitive::Primitive,
+    material::Material, model_buffer_info::ModelBufferIndo, primitive::Primitive,
     renderer::Configuration,
-    uniform_buffer::create_uniform_buffer,
 };
 
+#[derive(Getters, CopyGetters)]
 pub struct Mesh {
+    #[getset(get = "pub")]
     primitives: Vec<Primitive>,
-    transform_bind_group: BindGroupInfo,
-    transform: Transform,
+    /// Index of the node this mesh is drawn at, selecting its slot in the [`TransformStorage`]
+    #[getset(get_copy = "pub")]
+    node: usize,
 }
 
 impl Mesh {
+    /// `bind_group_layouts` are the scene wide groups of the color and the shadow pass, the
+    /// material is appended to them
     pub fn new(
         config: &Configuration,
-        camera: &Camera,
         mesh: gltf::Mesh,
-        node: gltf::Node,
+        node: &gltf::Node,
+        bind_group_layouts: &[&wgpu::BindGroupLayout],
+        shadow_bind_group_layouts: &[&wgpu::BindGroupLayout],
         buffer_info: &ModelBufferIndo,
+        materials: &[Material],
     ) -> Self {
-        let name = node.name().unwrap_or("Unnamed Mesh");
-
-        let decomposed = node.transform().decomposed();
-        let position = decomposed.0.into();
-        let rotation = Vec3::euler_from_quaternion_data(decomposed.1);
-        let scale = decomposed.2.into();
-
-        let transform = Transform::new(position, rotation, scale);
-
-        let buffer = create_uniform_buffer(
-            config,
-            &format!("Transform buffer for '{}'", name),
-            &Mat4::transform(&transform).bytes(),
-        );
-
-        let transform_bind_group = bindgroup::create_bindgroup(
-            config,
-            &[BindGroupEntryInfo::new(
-                wgpu::ShaderStages::VERTEX,
-                wgpu::BindingResource::Buffer(wgpu::BufferBinding {
-                    buffer: &buffer,
-                    offset: 0,
-                    size: None,
-                }),
-            )],
-            &format!("Transform bind group of '{}'", name),
-        );
-
         let primitives = mesh
             .primitives()
             .map(|primitive| {
@@ -57,28 +33,17 @@ impl Mesh {
                     config,
                     &primitive,
                     &mesh,
-                    &[&transform_bind_group.layout, &camera.bind_group().layout],
+                    bind_group_layouts,
+                    shadow_bind_group_layouts,
                     buffer_info,
+                    materials,
                 )
             })
             .collect();
 
         Self {
             primitives,
-            transform_bind_group,
-            transform,
-        }
-    }
-
-    pub fn render<'a>(
-        &'a self,
-        render_pass: &mut wgpu::RenderPass<'a>,
-        buffer_info: &'a ModelBufferIndo,
-    ) {
-        render_pass.set_bind_group(0, &self.transform_bind_group.group, &[]);
-
-        for primitive in &self.primitives {
-            primitive.render(render_pass, buffer_info);
+            node: node.index(),
         }
     }
 }
@@ -1,9 +1,18 @@
+use std::collections::HashMap;
+
 use getset::Getters;
-use gltf::Gltf;
+use gltf::{
+    accessor::{sparse::IndexType, DataType},
+    buffer::{self, Target},
+    mesh::Mode,
+    Accessor, Document,
+};
 use wgpu::util::DeviceExt;
 
 use crate::{
+    graph::GraphError,
     renderer::Configuration,
+    vertex,
     view::{ViewInfo, ViewType},
 };
 
@@ -13,49 +22,359 @@ pub struct ModelBufferIndo {
     vertex_buffer: wgpu::Buffer,
     #[getset(get = "pub")]
     index_buffer: wgpu::Buffer,
-    #[getset(get = "pub")]
-    views: Vec<ViewType>,
+    /// Where the data of each accessor ended up, indexed by accessor index.
+    /// Accessors which are not used for rendering are not uploaded.
+    views: Vec<Option<ViewType>>,
+    /// Indices generated for primitives whose mode wgpu can't draw directly,
+    /// keyed by mesh and primitive index
+    converted_indices: HashMap<(usize, usize), ViewInfo>,
+    /// Values for attributes a shader reads but a mesh lacks, see [`vertex::default_semantics`]
+    defaults: Vec<(ViewInfo, wgpu::VertexFormat)>,
 }
 
 impl ModelBufferIndo {
-    pub fn new(config: &Configuration, gltf: &Gltf, raw_buffer_data: &[u8]) -> Self {
+    /// Uploads the data of every accessor used by a primitive into one vertex and one index buffer.
+    /// <br>
+    /// The data is repacked tightly per accessor, so buffer view offsets, interleaved strides and
+    /// multiple buffers don't matter to the rest of the renderer.
+    pub fn new(
+        config: &Configuration,
+        document: &Document,
+        buffers: &[buffer::Data],
+    ) -> Result<Self, GraphError> {
         let mut vertex_buffer_data = Vec::new();
         let mut index_buffer_data = Vec::new();
-        let mut views = Vec::new();
-        let mut raw_buffer_index = 0;
 
-        for view in gltf.views() {
-            let target = view.target().expect("Buffer views need to have a target");
+        let usages = accessor_usages(document)?;
+
+        let views = document
+            .accessors()
+            .zip(usages)
+            .map(|(accessor, usage)| {
+                let Some(usage) = usage else {
+                    return Ok(None);
+                };
+
+                let buffer_data = match usage {
+                    Target::ArrayBuffer => &mut vertex_buffer_data,
+                    Target::ElementArrayBuffer => &mut index_buffer_data,
+                };
+
+                let mut data = read_accessor(&accessor, buffers)?;
+                let mut stride = accessor.size() as u64;
+
+                // wgpu has no 8 bit index format
+                if usage == Target::ElementArrayBuffer && accessor.data_type() == DataType::U8 {
+                    data = data
+                        .iter()
+                        .flat_map(|&i| (i as u16).to_le_bytes())
+                        .collect();
+                    stride = 2;
+                }
+
+                if usage == Target::ArrayBuffer {
+                    data = vertex::pad(&accessor, data);
+                    if let Some((format, _)) = vertex::format(&accessor) {
+                        stride = format.size();
+                    }
+                }
+
+                let view_info = ViewInfo::new(data.len() as u64, buffer_data.len() as u64, stride);
+
+                buffer_data.extend_from_slice(&data);
+                // keep every view aligned for `set_vertex_buffer` and `set_index_buffer`
+                buffer_data.resize(buffer_data.len().next_multiple_of(4), 0);
 
-            let buffer_data = match target {
-                gltf::buffer::Target::ArrayBuffer => &mut vertex_buffer_data,
-                gltf::buffer::Target::ElementArrayBuffer => &mut index_buffer_data,
-            };
+                Ok(Some(match usage {
+                    Target::ArrayBuffer => ViewType::Vertex(view_info),
+                    Target::ElementArrayBuffer => ViewType::Index(
+                        view_info,
+                        match stride {
+                            4 => wgpu::IndexFormat::Uint32,
+                            _ => wgpu::IndexFormat::Uint16,
+                        },
+                    ),
+                }))
+            })
+            .collect::<Result<_, GraphError>>()?;
 
-            let start = raw_buffer_index;
-            let len = view.length();
-            let data = &raw_buffer_data[start..start + len];
+        let mut converted_indices = HashMap::new();
 
-            let view_info = ViewInfo::new(len as u64, buffer_data.len() as u64);
+        for mesh in document.meshes() {
+            for primitive in mesh.primitives() {
+                let Some(indices) = convert_to_list(&primitive, buffers)? else {
+                    continue;
+                };
 
-            match target {
-                gltf::buffer::Target::ArrayBuffer => views.push(ViewType::Vertex(view_info)),
-                gltf::buffer::Target::ElementArrayBuffer => views.push(ViewType::Index(view_info)),
-            };
+                let data: Vec<_> = indices.iter().flat_map(|i| i.to_le_bytes()).collect();
+                let view_info = ViewInfo::new(data.len() as u64, index_buffer_data.len() as u64, 4);
 
-            buffer_data.append(&mut data.to_vec());
-            raw_buffer_index += len;
+                index_buffer_data.extend_from_slice(&data);
+                converted_indices.insert((mesh.index(), primitive.index()), view_info);
+            }
         }
 
+        let defaults = vertex::default_semantics()
+            .iter()
+            .map(|semantic| {
+                let (format, value) = vertex::default_value(semantic);
+                let view_info = ViewInfo::new(16, vertex_buffer_data.len() as u64, 16);
+
+                vertex_buffer_data.extend(value.iter().flat_map(|v| v.to_le_bytes()));
+                (view_info, format)
+            })
+            .collect();
+
         let vertex_buffer = create_buffer(config, &vertex_buffer_data, wgpu::BufferUsages::VERTEX);
         let index_buffer = create_buffer(config, &index_buffer_data, wgpu::BufferUsages::INDEX);
 
-        Self {
+        Ok(Self {
             vertex_buffer,
             index_buffer,
             views,
+            converted_indices,
+            defaults,
+        })
+    }
+
+    /// Where the data of `accessor` is located in the vertex or index buffer, `None` if it isn't
+    /// used by any primitive and therefore not uploaded
+    pub fn view(&self, accessor: &Accessor) -> Option<ViewType> {
+        self.views.get(accessor.index()).copied().flatten()
+    }
+
+    /// Where the vertex data of `accessor` is located, `None` if it isn't vertex data
+    pub fn vertex_view(&self, accessor: &Accessor) -> Option<ViewInfo> {
+        match self.view(accessor)? {
+            ViewType::Vertex(info) => Some(info),
+            ViewType::Index(..) => None,
         }
     }
+
+    /// The value bound for an attribute a shader reads but a mesh lacks
+    pub fn default_view(&self, semantic: &gltf::Semantic) -> (ViewInfo, wgpu::VertexFormat) {
+        self.defaults[vertex::default_index(semantic)]
+    }
+
+    /// Where the indices to draw a primitive with are located, `None` for non-indexed primitives
+    pub fn indices(
+        &self,
+        mesh: &gltf::Mesh,
+        primitive: &gltf::Primitive,
+    ) -> Option<(ViewInfo, wgpu::IndexFormat)> {
+        if let Some(info) = self
+            .converted_indices
+            .get(&(mesh.index(), primitive.index()))
+        {
+            return Some((*info, wgpu::IndexFormat::Uint32));
+        }
+
+        // `new` rejects accessors used as both indices and vertex data
+        match self.view(&primitive.indices()?)? {
+            ViewType::Index(info, format) => Some((info, format)),
+            ViewType::Vertex(_) => None,
+        }
+    }
+}
+
+/// Line loops and triangle fans can't be drawn by wgpu, so they are converted to line and
+/// triangle lists. Returns `None` for primitives which don't need a conversion, and for ones with
+/// too few indices to form a single line or triangle, which are left to draw nothing.
+fn convert_to_list(
+    primitive: &gltf::Primitive,
+    buffers: &[buffer::Data],
+) -> Result<Option<Vec<u32>>, GraphError> {
+    let mode = primitive.mode();
+    if !matches!(mode, Mode::LineLoop | Mode::TriangleFan) {
+        return Ok(None);
+    }
+
+    let indices: Vec<u32> = match primitive.indices() {
+        Some(accessor) => {
+            let size = accessor.size();
+            read_accessor(&accessor, buffers)?
+                .chunks_exact(size)
+                .map(|index| {
+                    let mut bytes = [0; 4];
+                    bytes[..size].copy_from_slice(index);
+                    u32::from_le_bytes(bytes)
+                })
+                .collect()
+        }
+        None => {
+            let count = primitive
+                .get(&gltf::Semantic::Positions)
+                .map(|positions| positions.count())
+                .unwrap_or_default();
+            (0..count as u32).collect()
+        }
+    };
+
+    let n = indices.len();
+
+    Ok(match mode {
+        Mode::LineLoop if n >= 2 => Some(
+            (0..n)
+                .flat_map(|i| [indices[i], indices[(i + 1) % n]])
+                .collect(),
+        ),
+        Mode::TriangleFan if n >= 3 => Some(
+            (1..n - 1)
+                .flat_map(|i| [indices[0], indices[i], indices[i + 1]])
+                .collect(),
+        ),
+        _ => None,
+    })
+}
+
+/// Infers for every accessor whether it holds vertex or index data, from how primitives use it.
+/// <br>
+/// Buffer view targets are optional in glTF, so they can't be relied upon. An accessor used both
+/// ways would have to be uploaded twice, which no exporter does, so it's rejected.
+fn accessor_usages(document: &Document) -> Result<Vec<Option<Target>>, GraphError> {
+    let mut usages = vec![None; document.accessors().len()];
+
+    for primitive in document.meshes().flat_map(|mesh| mesh.primitives()) {
+        let attributes = primitive.attributes().map(|(_, accessor)| accessor).chain(
+            primitive.morph_targets().flat_map(|target| {
+                target
+                    .positions()
+                    .into_iter()
+                    .chain(target.normals())
+                    .chain(target.tangents())
+            }),
+        );
+
+        let accessors = attributes
+            .map(|accessor| (accessor, Target::ArrayBuffer))
+            .chain(
+                primitive
+                    .indices()
+                    .map(|accessor| (accessor, Target::ElementArrayBuffer)),
+            );
+
+        for (accessor, target) in accessors {
+            let usage = &mut usages[accessor.index()];
+            if usage.is_some_and(|usage| usage != target) {
+                return Err(GraphError::InvalidAccessor(format!(
+                    "accessor {} is used as both vertex and index data",
+                    accessor.index()
+                )));
+            }
+            *usage = Some(target);
+        }
+    }
+
+    Ok(usages)
+}
+
+/// Reads all elements of an accessor into a tightly packed byte vector.
+/// <br>
+/// Offsets, strides and sparse indices come straight from the file, so every read is checked
+/// against the buffer view it reads from.
+fn read_accessor(accessor: &Accessor, buffers: &[buffer::Data]) -> Result<Vec<u8>, GraphError> {
+    let element_size = accessor.size();
+    let count = accessor.count();
+
+    let invalid = |reason: &str| {
+        GraphError::InvalidAccessor(format!("accessor {}: {reason}", accessor.index()))
+    };
+    let read = |view: &buffer::View, offset, stride, element_size, count| {
+        let buffer = buffers
+            .get(view.buffer().index())
+            .ok_or_else(|| invalid("its buffer is missing"))?;
+        let view_data = view
+            .offset()
+            .checked_add(view.length())
+            .and_then(|end| buffer.get(view.offset()..end))
+            .ok_or_else(|| invalid("its buffer view runs past the end of its buffer"))?;
+        read_strided(view_data, offset, stride, element_size, count)
+            .ok_or_else(|| invalid("reads past the end of its buffer view"))
+    };
+
+    // accessors without a view are initialized with zeros
+    let mut data = match accessor.view() {
+        Some(view) => read(
+            &view,
+            accessor.offset(),
+            view.stride().unwrap_or(element_size),
+            element_size,
+            count,
+        )?,
+        None => vec![0; element_size * count],
+    };
+
+    if let Some(sparse) = accessor.sparse() {
+        let indices = sparse.indices();
+        let index_size = match indices.index_type() {
+            IndexType::U8 => 1,
+            IndexType::U16 => 2,
+            IndexType::U32 => 4,
+        };
+        let index_data = read(
+            &indices.view(),
+            indices.offset(),
+            index_size,
+            index_size,
+            sparse.count(),
+        )?;
+
+        let values = sparse.values();
+        let value_data = read(
+            &values.view(),
+            values.offset(),
+            element_size,
+            element_size,
+            sparse.count(),
+        )?;
+
+        for (index, value) in index_data
+            .chunks_exact(index_size)
+            .zip(value_data.chunks_exact(element_size))
+        {
+            let mut bytes = [0; 4];
+            bytes[..index_size].copy_from_slice(index);
+            let index = u32::from_le_bytes(bytes) as usize;
+            if index >= count {
+                return Err(invalid("a sparse index is past its last element"));
+            }
+
+            let start = index * element_size;
+            data[start..start + element_size].copy_from_slice(value);
+        }
+    }
+
+    Ok(data)
+}
+
+/// Copies `count` elements of `element_size` bytes, which are `stride` bytes apart.
+/// <br>
+/// `None` if the last element ends past `data`.
+fn read_strided(
+    data: &[u8],
+    offset: usize,
+    stride: usize,
+    element_size: usize,
+    count: usize,
+) -> Option<Vec<u8>> {
+    if count == 0 {
+        return Some(Vec::new());
+    }
+
+    let end = (count - 1)
+        .checked_mul(stride)?
+        .checked_add(offset)?
+        .checked_add(element_size)?;
+    let data = data.get(offset..end)?;
+
+    let mut result = Vec::with_capacity(element_size * count);
+
+    for i in 0..count {
+        let start = i * stride;
+        result.extend_from_slice(&data[start..start + element_size]);
+    }
+
+    Some(result)
 }
 
 fn create_buffer(
@@ -68,6 +387,62 @@ fn create_buffer(
         .create_buffer_init(&wgpu::util::BufferInitDescriptor {
             label: Some(&format!("buffer with usage {:?}", usage)),
             contents: buffer_data,
-            usage: usage,
+            usage,
         })
 }
+
+#[cfg(test)]
+mod tests {
+    use gltf::Gltf;
+
+    use super::*;
+
+    /// A document with a 16 byte buffer split into two views of 8 bytes, and `accessors`
+    fn document(accessors: &str) -> Document {
+        let json = format!(
+            r#"{{
+                "asset": {{ "version": "2.0" }},
+                "buffers": [{{ "byteLength": 16 }}],
+                "bufferViews": [
+                    {{ "buffer": 0, "byteOffset": 0, "byteLength": 8 }},
+                    {{ "buffer": 0, "byteOffset": 8, "byteLength": 8 }}
+                ],
+                "accessors": [{accessors}]
+            }}"#
+        );
+
+        Gltf::from_slice(json.as_bytes())
+            .expect("document is valid")
+            .document
+    }
+
+    fn buffers() -> Vec<buffer::Data> {
+        vec![buffer::Data((0..16).collect())]
+    }
+
+    #[test]
+    fn reads_within_the_view() {
+        let document = document(
+            r#"{ "bufferView": 1, "byteOffset": 4, "componentType": 5121, "count": 4, "type": "SCALAR" }"#,
+        );
+        let accessor = document.accessors().next().expect("there is an accessor");
+
+        let data = read_accessor(&accessor, &buffers()).expect("accessor is in bounds");
+        assert_eq!(data, [12, 13, 14, 15]);
+    }
+
+    #[test]
+    fn rejects_reads_past_the_view() {
+        // ends inside the buffer, but reads the first bytes of the next view
+        let document = document(
+            r#"{ "bufferView": 0, "byteOffset": 4, "componentType": 5121, "count": 8, "type": "SCALAR" }"#,
+        );
+        let accessor = document.accessors().next().expect("there is an accessor");
+
+        let err = read_accessor(&accessor, &buffers()).expect_err("accessor overruns its view");
+        assert!(
+            matches!(&err, GraphError::InvalidAccessor(reason) if reason.contains("buffer view")),
+            "{err}"
+        );
+    }
+}
@@ -0,0 +1,69 @@
+use getset::{CopyGetters, Getters};
+
+use crate::math::{mat::Mat4, transform::Transform};
+
+/// A node of the scene graph, its links are indices into [`crate::graph::Graph::nodes`]
+#[derive(Getters, CopyGetters)]
+pub struct Node {
+    #[getset(get = "pub")]
+    name: String,
+    #[getset(get_copy = "pub")]
+    parent: Option<usize>,
+    #[getset(get = "pub")]
+    children: Vec<usize>,
+
+    /// Transform relative to the parent
+    #[getset(get = "pub")]
+    transform: Transform,
+    #[getset(get_copy = "pub")]
+    local: Mat4,
+    /// Transform relative to the scene root, only valid if the node is not dirty
+    #[getset(get_copy = "pub")]
+    world: Mat4,
+    /// Set when the local transform changed and the world transform of the subtree is outdated
+    #[getset(get_copy = "pub")]
+    dirty: bool,
+
+    /// Index of the glTF mesh drawn at this node
+    #[getset(get_copy = "pub")]
+    mesh: Option<usize>,
+    /// Index of the glTF camera attached to this node
+    #[getset(get_copy = "pub")]
+    camera: Option<usize>,
+    /// Index of the glTF light attached to this node
+    #[getset(get_copy = "pub")]
+    light: Option<usize>,
+}
+
+impl Node {
+    pub fn new(node: &gltf::Node, parent: Option<usize>) -> Self {
+        let transform: Transform = node.transform().decomposed().into();
+        let local = Mat4::transform(&transform);
+
+        Self {
+            name: node.name().unwrap_or("Unnamed Node").to_owned(),
+            parent,
+            children: node.children().map(|child| child.index()).collect(),
+            transform,
+            local,
+            world: local,
+            dirty: true,
+            mesh: node.mesh().map(|mesh| mesh.index()),
+            camera: node.camera().map(|camera| camera.index()),
+            light: node.light().map(|light| light.index()),
+        }
+    }
+
+    /// Replaces the local transform, the world transform is updated with [`crate::graph::Graph::update`]
+    pub fn set_transform(&mut self, transform: Transform) {
+        self.local = Mat4::transform(&transform);
+        self.transform = transform;
+        self.dirty = true;
+    }
+
+    /// Recomputes the world transform from the world transform of the parent
+    pub(crate) fn propagate(&mut self, parent_world: &Mat4) {
+        self.world = *parent_world * self.local;
+        self.dirty = false;
+    }
+}
@@ -0,0 +1,99 @@
+use std::sync::mpsc;
+
+use getset::Getters;
+
+use crate::renderer::Configuration;
+
+/// The color target of a headless renderer, which can be read back to the CPU
+#[derive(Getters)]
+pub struct OffscreenTarget {
+    #[getset(get = "pub")]
+    texture: wgpu::Texture,
+    #[getset(get = "pub")]
+    view: wgpu::TextureView,
+}
+
+impl OffscreenTarget {
+    /// The format of the target, PNGs are stored in sRGB as well
+    pub const FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8UnormSrgb;
+
+    pub fn new(config: &Configuration) -> Self {
+        let texture = config.device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Offscreen target"),
+            size: wgpu::Extent3d {
+                width: config.size.width.max(1),
+                height: config.size.height.max(1),
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: Self::FORMAT,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+            view_formats: &[],
+        });
+
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+        Self { texture, view }
+    }
+
+    /// Copies the target into a buffer and waits until it can be read
+    pub fn read(&self, config: &Configuration) -> Result<image::RgbaImage, wgpu::BufferAsyncError> {
+        let size = self.texture.size();
+        let unpadded_bytes_per_row = 4 * size.width;
+        // rows of texture to buffer copies have to be aligned to 256 bytes
+        let bytes_per_row =
+            unpadded_bytes_per_row.next_multiple_of(wgpu::COPY_BYTES_PER_ROW_ALIGNMENT);
+
+        let buffer = config.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Offscreen readback buffer"),
+            size: (bytes_per_row * size.height) as u64,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+
+        let mut encoder = config
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("Readback Encoder"),
+            });
+
+        encoder.copy_texture_to_buffer(
+            self.texture.as_image_copy(),
+            wgpu::ImageCopyBuffer {
+                buffer: &buffer,
+                layout: wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: Some(bytes_per_row),
+                    rows_per_image: Some(size.height),
+                },
+            },
+            size,
+        );
+
+        config.queue.submit(std::iter::once(encoder.finish()));
+
+        let slice = buffer.slice(..);
+        let (sender, receiver) = mpsc::channel();
+        slice.map_async(wgpu::MapMode::Read, move |result| {
+            // the receiver outlives the wait below
+            let _ = sender.send(result);
+        });
+        config.device.poll(wgpu::Maintain::Wait);
+        receiver
+            .recv()
+            .expect("the map callback runs while polling")?;
+
+        let pixels = slice
+            .get_mapped_range()
+            .chunks_exact(bytes_per_row as usize)
+            .flat_map(|row| &row[..unpadded_bytes_per_row as usize])
+            .copied()
+            .collect();
+        buffer.unmap();
+
+        Ok(image::RgbaImage::from_raw(size.width, size.height, pixels)
+            .expect("the buffer holds exactly one image"))
+    }
+}
@@ -0,0 +1,522 @@
+// Metallic-roughness PBR following the glTF 2.0 specification, appendix B
+// https://registry.khronos.org/glTF/specs/2.0/glTF-2.0.html#appendix-b-brdf-implementation
+
+const PI: f32 = 3.14159265359;
+
+const LIGHT_DIRECTIONAL: u32 = 0u;
+const LIGHT_POINT: u32 = 1u;
+const LIGHT_SPOT: u32 = 2u;
+
+// Indirect light without an environment map
+const AMBIENT: vec3<f32> = vec3<f32>(0.03, 0.03, 0.03);
+
+// Tints of the cascade debug view, from the closest to the farthest cascade
+const CASCADE_TINTS: array<vec3<f32>, 4> = array<vec3<f32>, 4>(
+    vec3<f32>(1.0, 0.3, 0.3),
+    vec3<f32>(0.3, 1.0, 0.3),
+    vec3<f32>(0.3, 0.3, 1.0),
+    vec3<f32>(1.0, 1.0, 0.3),
+);
+
+struct TransformUniform {
+    model: mat4x4<f32>,
+    // inverse transpose of the model matrix
+    normal: mat4x4<f32>,
+}
+
+struct CameraUniform {
+    view: mat4x4<f32>,
+    projection: mat4x4<f32>,
+    view_projection: mat4x4<f32>,
+    position: vec4<f32>,
+}
+
+// A KHR_lights_punctual light, see `LightData::bytes`
+struct Light {
+    position: vec3<f32>,
+    // 0 for an infinite range
+    range: f32,
+    // the -z axis of the light node
+    direction: vec3<f32>,
+    kind: u32,
+    color: vec3<f32>,
+    intensity: f32,
+    // the cone falloff maps the cosine to the axis into 0..1 with these
+    spot_scale: f32,
+    spot_offset: f32,
+    // first cascade layer of directional lights, first atlas tile of point and spot lights,
+    // -1 without shadows
+    shadow: i32,
+}
+
+const MAX_SHADOW_LAYERS: u32 = 16u;
+
+// Cascaded shadow maps of the directional lights, see `ShadowMaps::update`
+struct ShadowUniform {
+    view_projections: array<mat4x4<f32>, MAX_SHADOW_LAYERS>,
+    // view depth where every cascade ends
+    splits: vec4<f32>,
+    // world space size of a texel of every cascade
+    texel_sizes: vec4<f32>,
+    cascade_count: u32,
+    pcf_radius: u32,
+    normal_bias: f32,
+    debug_cascades: u32,
+}
+
+// A tile of the shadow atlas, a spot light or one cube face of a point light
+struct LocalShadow {
+    view_projection: mat4x4<f32>,
+    // offset in xy and size in zw, in atlas uv
+    rect: vec4<f32>,
+    // world space size of a texel at a distance of one
+    texel_scale: f32,
+}
+
+// See `Environment::new`
+struct EnvironmentUniform {
+    intensity: f32,
+    // of the specular cube
+    max_mip: f32,
+    enabled: u32,
+}
+
+struct MaterialUniform {
+    base_color_factor: vec4<f32>,
+    emissive_factor: vec3<f32>,
+    metallic_factor: f32,
+    roughness_factor: f32,
+    normal_scale: f32,
+    occlusion_strength: f32,
+    alpha_cutoff: f32,
+    // the TEXCOORD_n set every texture is sampled with
+    base_color_tex_coord: u32,
+    metallic_roughness_tex_coord: u32,
+    normal_tex_coord: u32,
+    occlusion_tex_coord: u32,
+    emissive_tex_coord: u32,
+    // 0 opaque, 1 mask, 2 blend
+    alpha_mode: u32,
+    // without one, the interpolated normal is used as it is
+    has_normal_texture: u32,
+}
+
+@group(0) @binding(0)
+var<uniform> transform: TransformUniform;
+
+@group(1) @binding(0)
+var<uniform> camera: CameraUniform;
+
+@group(2) @binding(0)
+var<storage, read> lights: array<Light>;
+@group(2) @binding(1)
+var<uniform> shadow: ShadowUniform;
+@group(2) @binding(2)
+var shadow_maps: texture_depth_2d_array;
+@group(2) @binding(3)
+var shadow_sampler: sampler_comparison;
+@group(2) @binding(4)
+var<storage, read> local_shadows: array<LocalShadow>;
+@group(2) @binding(5)
+var shadow_atlas: texture_depth_2d;
+@group(2) @binding(6)
+var<uniform> environment: EnvironmentUniform;
+// prefiltered with the roughness increasing per mip
+@group(2) @binding(7)
+var specular_map: texture_cube<f32>;
+@group(2) @binding(8)
+var irradiance_map: texture_cube<f32>;
+// scale and bias of F0, by n_dot_v along x and roughness along y
+@group(2) @binding(9)
+var brdf_lut: texture_2d<f32>;
+@group(2) @binding(10)
+var environment_sampler: sampler;
+
+@group(3) @binding(0)
+var<uniform> material: MaterialUniform;
+@group(3) @binding(1)
+var base_color_texture: texture_2d<f32>;
+@group(3) @binding(2)
+var base_color_sampler: sampler;
+// metalness in blue, roughness in green
+@group(3) @binding(3)
+var metallic_roughness_texture: texture_2d<f32>;
+@group(3) @binding(4)
+var metallic_roughness_sampler: sampler;
+@group(3) @binding(5)
+var normal_texture: texture_2d<f32>;
+@group(3) @binding(6)
+var normal_sampler: sampler;
+// occlusion in red
+@group(3) @binding(7)
+var occlusion_texture: texture_2d<f32>;
+@group(3) @binding(8)
+var occlusion_sampler: sampler;
+@group(3) @binding(9)
+var emissive_texture: texture_2d<f32>;
+@group(3) @binding(10)
+var emissive_sampler: sampler;
+
+// Vertex shader
+
+struct VertexInput {
+    @location(0) position: vec3<f32>,
+    @location(1) normal: vec3<f32>,
+    @location(2) tex_coords_0: vec2<f32>,
+    @location(3) tangent: vec4<f32>,
+    @location(4) tex_coords_1: vec2<f32>,
+    @location(5) color: vec4<f32>,
+}
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) world_position: vec3<f32>,
+    @location(1) normal: vec3<f32>,
+    @location(2) tangent: vec4<f32>,
+    @location(3) tex_coords_0: vec2<f32>,
+    @location(4) tex_coords_1: vec2<f32>,
+    @location(5) color: vec4<f32>,
+}
+
+@vertex
+fn vs_main(
+    in: VertexInput,
+) -> VertexOutput {
+    var out: VertexOutput;
+
+    let world_position = transform.model * vec4<f32>(in.position, 1.0);
+
+    out.clip_position = camera.view_projection * world_position;
+    out.world_position = world_position.xyz;
+    out.normal = (transform.normal * vec4<f32>(in.normal, 0.0)).xyz;
+    out.tangent = vec4<f32>((transform.model * vec4<f32>(in.tangent.xyz, 0.0)).xyz, in.tangent.w);
+    out.tex_coords_0 = in.tex_coords_0;
+    out.tex_coords_1 = in.tex_coords_1;
+    out.color = in.color;
+    return out;
+}
+
+// Fragment shader
+
+// Trowbridge-Reitz / GGX normal distribution
+fn distribution_ggx(n_dot_h: f32, alpha: f32) -> f32 {
+    let alpha_2 = alpha * alpha;
+    let d = n_dot_h * n_dot_h * (alpha_2 - 1.0) + 1.0;
+    return alpha_2 / (PI * d * d);
+}
+
+// Height correlated Smith masking-shadowing, including the 1 / (4 n.l n.v) of the specular BRDF
+fn visibility_smith_ggx(n_dot_l: f32, n_dot_v: f32, alpha: f32) -> f32 {
+    let alpha_2 = alpha * alpha;
+    let ggx_v = n_dot_l * sqrt(n_dot_v * n_dot_v * (1.0 - alpha_2) + alpha_2);
+    let ggx_l = n_dot_v * sqrt(n_dot_l * n_dot_l * (1.0 - alpha_2) + alpha_2);
+    let ggx = ggx_v + ggx_l;
+    if ggx > 0.0 {
+        return 0.5 / ggx;
+    }
+    return 0.0;
+}
+
+fn fresnel_schlick(f0: vec3<f32>, v_dot_h: f32) -> vec3<f32> {
+    return f0 + (vec3<f32>(1.0) - f0) * pow(clamp(1.0 - v_dot_h, 0.0, 1.0), 5.0);
+}
+
+// Cook-Torrance BRDF times n.l, the radiance reflected towards `v` for light arriving from `l`
+fn brdf(
+    n: vec3<f32>,
+    v: vec3<f32>,
+    l: vec3<f32>,
+    base_color: vec3<f32>,
+    metallic: f32,
+    roughness: f32,
+) -> vec3<f32> {
+    let h = normalize(l + v);
+    let n_dot_l = clamp(dot(n, l), 0.0, 1.0);
+    let n_dot_v = clamp(dot(n, v), 0.0, 1.0);
+    let n_dot_h = clamp(dot(n, h), 0.0, 1.0);
+    let v_dot_h = clamp(dot(v, h), 0.0, 1.0);
+
+    let alpha = roughness * roughness;
+
+    let f0 = mix(vec3<f32>(0.04), base_color, metallic);
+    let fresnel = fresnel_schlick(f0, v_dot_h);
+
+    let diffuse = (vec3<f32>(1.0) - fresnel) * (1.0 - metallic) * base_color / PI;
+    let specular = fresnel * distribution_ggx(n_dot_h, alpha) * visibility_smith_ggx(n_dot_l, n_dot_v, alpha);
+
+    return (diffuse + specular) * n_dot_l;
+}
+
+// Smoothly reaches zero at the range, without a range the inverse square law applies unchanged
+// https://github.com/KhronosGroup/glTF/tree/main/extensions/2.0/Khronos/KHR_lights_punctual#range-property
+fn range_attenuation(distance: f32, range: f32) -> f32 {
+    let inverse_square = 1.0 / max(distance * distance, 1e-4);
+    if range <= 0.0 {
+        return inverse_square;
+    }
+    let ratio = distance / range;
+    return clamp(1.0 - ratio * ratio * ratio * ratio, 0.0, 1.0) * inverse_square;
+}
+
+// The light arriving at `position`, its direction towards the light in `l`
+fn incoming_light(light: Light, position: vec3<f32>, l: ptr<function, vec3<f32>>) -> vec3<f32> {
+    if light.kind == LIGHT_DIRECTIONAL {
+        *l = -light.direction;
+        return light.color * light.intensity;
+    }
+
+    let to_light = light.position - position;
+    let distance = length(to_light);
+    *l = to_light / max(distance, 1e-4);
+
+    var attenuation = range_attenuation(distance, light.range);
+    if light.kind == LIGHT_SPOT {
+        let cone = clamp(dot(light.direction, -*l) * light.spot_scale + light.spot_offset, 0.0, 1.0);
+        attenuation *= cone * cone;
+    }
+
+    return light.color * light.intensity * attenuation;
+}
+
+// The first cascade reaching beyond `view_depth`, `cascade_count` if all end before it
+fn cascade_index(view_depth: f32) -> u32 {
+    for (var i = 0u; i < shadow.cascade_count; i++) {
+        if view_depth < shadow.splits[i] {
+            return i;
+        }
+    }
+    return shadow.cascade_count;
+}
+
+// How much of `light` reaches `position`, filtered over (2 * pcf_radius + 1)^2 samples
+fn shadow_factor(light: Light, position: vec3<f32>, normal: vec3<f32>, cascade: u32) -> f32 {
+    if light.shadow < 0 {
+        return 1.0;
+    }
+
+    switch light.kind {
+        case LIGHT_POINT: {
+            let tile = u32(light.shadow) + cube_face(position - light.position);
+            return local_shadow(tile, position, normal, distance(position, light.position));
+        }
+        case LIGHT_SPOT: {
+            return local_shadow(u32(light.shadow), position, normal, distance(position, light.position));
+        }
+        default: {
+            return cascade_shadow(u32(light.shadow), position, normal, cascade);
+        }
+    }
+}
+
+fn cascade_shadow(first_layer: u32, position: vec3<f32>, normal: vec3<f32>, cascade: u32) -> f32 {
+    if cascade >= shadow.cascade_count {
+        return 1.0;
+    }
+    let layer = first_layer + cascade;
+
+    // moving the receiver off its surface avoids shadow acne on surfaces facing away from the light
+    let offset = normal * shadow.normal_bias * shadow.texel_sizes[cascade];
+    let clip = shadow.view_projections[layer] * vec4<f32>(position + offset, 1.0);
+    let uv = clip.xy * vec2<f32>(0.5, -0.5) + 0.5;
+    if any(uv < vec2<f32>(0.0)) || any(uv > vec2<f32>(1.0)) || clip.z > 1.0 {
+        return 1.0;
+    }
+
+    let texel = 1.0 / vec2<f32>(textureDimensions(shadow_maps));
+    let radius = i32(shadow.pcf_radius);
+    var lit = 0.0;
+    for (var y = -radius; y <= radius; y++) {
+        for (var x = -radius; x <= radius; x++) {
+            // the level variant, as the light loop is not in uniform control flow
+            lit += textureSampleCompareLevel(
+                shadow_maps,
+                shadow_sampler,
+                uv + vec2<f32>(f32(x), f32(y)) * texel,
+                layer,
+                clip.z,
+            );
+        }
+    }
+    let samples = f32((2 * radius + 1) * (2 * radius + 1));
+    return lit / samples;
+}
+
+// The cube face seeing `direction` from the light, in the order of `CUBE_FACES` in `shadow.rs`
+fn cube_face(direction: vec3<f32>) -> u32 {
+    let a = abs(direction);
+    if a.x >= a.y && a.x >= a.z {
+        return select(1u, 0u, direction.x > 0.0);
+    }
+    if a.y >= a.z {
+        return select(3u, 2u, direction.y > 0.0);
+    }
+    return select(5u, 4u, direction.z > 0.0);
+}
+
+fn local_shadow(tile: u32, position: vec3<f32>, normal: vec3<f32>, distance: f32) -> f32 {
+    let local = local_shadows[tile];
+
+    let offset = normal * shadow.normal_bias * local.texel_scale * distance;
+    let clip = local.view_projection * vec4<f32>(position + offset, 1.0);
+    if clip.w <= 0.0 {
+        return 1.0;
+    }
+    let ndc = clip.xyz / clip.w;
+    let uv = ndc.xy * vec2<f32>(0.5, -0.5) + 0.5;
+    if any(uv < vec2<f32>(0.0)) || any(uv > vec2<f32>(1.0)) || ndc.z > 1.0 {
+        return 1.0;
+    }
+
+    let texel = 1.0 / vec2<f32>(textureDimensions(shadow_atlas));
+    let atlas_uv = local.rect.xy + uv * local.rect.zw;
+    // samples must not reach into the neighbouring tiles
+    let low = local.rect.xy + texel * 0.5;
+    let high = local.rect.xy + local.rect.zw - texel * 0.5;
+
+    let radius = i32(shadow.pcf_radius);
+    var lit = 0.0;
+    for (var y = -radius; y <= radius; y++) {
+        for (var x = -radius; x <= radius; x++) {
+            let sample_uv = clamp(atlas_uv + vec2<f32>(f32(x), f32(y)) * texel, low, high);
+            lit += textureSampleCompareLevel(shadow_atlas, shadow_sampler, sample_uv, ndc.z);
+        }
+    }
+    let samples = f32((2 * radius + 1) * (2 * radius + 1));
+    return lit / samples;
+}
+
+// Image based lighting with the split sum approximation, or the constant ambient term without an
+// environment map
+fn indirect_light(n: vec3<f32>, v: vec3<f32>, base_color: vec3<f32>, metallic: f32, roughness: f32) -> vec3<f32> {
+    if environment.enabled == 0u {
+        return AMBIENT * base_color;
+    }
+
+    let n_dot_v = clamp(dot(n, v), 0.0, 1.0);
+    let f0 = mix(vec3<f32>(0.04), base_color, metallic);
+    // fresnel at the average angle of the lobe, rough surfaces reflect less at grazing angles
+    let fresnel = f0 + (max(vec3<f32>(1.0 - roughness), f0) - f0) * pow(1.0 - n_dot_v, 5.0);
+
+    let irradiance = textureSampleLevel(irradiance_map, environment_sampler, n, 0.0).rgb;
+    let diffuse = (vec3<f32>(1.0) - fresnel) * (1.0 - metallic) * base_color * irradiance;
+
+    let r = reflect(-v, n);
+    let prefiltered = textureSampleLevel(specular_map, environment_sampler, r, roughness * environment.max_mip).rgb;
+    let scale_bias = textureSampleLevel(brdf_lut, environment_sampler, vec2<f32>(n_dot_v, roughness), 0.0).rg;
+    let specular = prefiltered * (f0 * scale_bias.x + scale_bias.y);
+
+    return (diffuse + specular) * environment.intensity;
+}
+
+fn tex_coords(in: VertexOutput, tex_coord: u32) -> vec2<f32> {
+    return select(in.tex_coords_0, in.tex_coords_1, tex_coord == 1u);
+}
+
+// The perturbed normal, `n` being the interpolated geometric normal facing the viewer
+fn normal_mapped(in: VertexOutput, n: vec3<f32>, front_facing: bool) -> vec3<f32> {
+    // uniform control flow, the material is the same for the whole draw
+    if material.has_normal_texture == 0u {
+        return n;
+    }
+
+    let uv = tex_coords(in, material.normal_tex_coord);
+
+    // without vertex tangents, they are derived from the change of texture coordinates
+    // like the glTF sample viewer does
+    var uv_dx = dpdx(uv);
+    let uv_dy = dpdy(uv);
+    if length(uv_dx) + length(uv_dy) <= 1e-6 {
+        uv_dx = vec2<f32>(1.0, 0.0);
+    }
+    let position_dx = dpdx(in.world_position);
+    let position_dy = dpdy(in.world_position);
+    let det = uv_dx.x * uv_dy.y - uv_dy.x * uv_dx.y;
+    var derived: vec3<f32>;
+    if abs(det) > 1e-12 {
+        derived = (uv_dy.y * position_dx - uv_dx.y * position_dy) / det;
+    } else {
+        // constant texture coordinates have no direction, any tangent of `n` will do
+        let axis = select(vec3<f32>(1.0, 0.0, 0.0), vec3<f32>(0.0, 1.0, 0.0), abs(n.x) > 0.9);
+        derived = cross(axis, n);
+    }
+
+    var t: vec3<f32>;
+    var handedness = 1.0;
+    if in.tangent.w == 0.0 {
+        t = derived;
+    } else {
+        t = in.tangent.xyz;
+        handedness = in.tangent.w;
+    }
+    if !front_facing {
+        t = -t;
+    }
+    t = normalize(t - n * dot(n, t));
+    let b = cross(n, t) * handedness;
+
+    let sampled = textureSample(normal_texture, normal_sampler, uv).xyz * 2.0 - 1.0;
+    let scaled = normalize(sampled * vec3<f32>(material.normal_scale, material.normal_scale, 1.0));
+
+    return normalize(mat3x3<f32>(t, b, n) * scaled);
+}
+
+@fragment
+fn fs_main(in: VertexOutput, @builtin(front_facing) front_facing: bool) -> @location(0) vec4<f32> {
+    let base_color = material.base_color_factor * in.color
+        * textureSample(base_color_texture, base_color_sampler, tex_coords(in, material.base_color_tex_coord));
+
+    let metallic_roughness = textureSample(
+        metallic_roughness_texture,
+        metallic_roughness_sampler,
+        tex_coords(in, material.metallic_roughness_tex_coord),
+    );
+    let metallic = clamp(material.metallic_factor * metallic_roughness.b, 0.0, 1.0);
+    let roughness = clamp(material.roughness_factor * metallic_roughness.g, 0.04, 1.0);
+
+    let occlusion = textureSample(occlusion_texture, occlusion_sampler, tex_coords(in, material.occlusion_tex_coord)).r;
+    let emissive = textureSample(emissive_texture, emissive_sampler, tex_coords(in, material.emissive_tex_coord)).rgb;
+
+    var geometric_normal = normalize(in.normal);
+    if !front_facing {
+        geometric_normal = -geometric_normal;
+    }
+    let n = normal_mapped(in, geometric_normal, front_facing);
+    let v = normalize(camera.position.xyz - in.world_position);
+
+    let view_depth = -(camera.view * vec4<f32>(in.world_position, 1.0)).z;
+    let cascade = cascade_index(view_depth);
+
+    var color = vec3<f32>(0.0);
+    for (var i = 0u; i < arrayLength(&lights); i++) {
+        var l: vec3<f32>;
+        let radiance = incoming_light(lights[i], in.world_position, &l);
+        let lit = shadow_factor(lights[i], in.world_position, geometric_normal, cascade);
+        color += brdf(n, v, l, base_color.rgb, metallic, roughness) * radiance * lit;
+    }
+    // occlusion only applies to indirect light
+    color += indirect_light(n, v, base_color.rgb, metallic, roughness) * mix(1.0, occlusion, material.occlusion_strength);
+    color += material.emissive_factor * emissive;
+
+    if shadow.debug_cascades != 0u && cascade < shadow.cascade_count {
+        // constant arrays can only be indexed dynamically through a copy
+        var tints = CASCADE_TINTS;
+        color *= tints[cascade];
+    }
+
+    var alpha = base_color.a;
+    switch material.alpha_mode {
+        case 1u: {
+            // discarding last keeps the texture samples above in uniform control flow
+            if alpha < material.alpha_cutoff {
+                discard;
+            }
+            alpha = 1.0;
+        }
+        case 2u: {}
+        default: {
+            alpha = 1.0;
+        }
+    }
+
+    return vec4<f32>(color, alpha);
+}
@@ -1,21 +1,247 @@
-use crate::renderer::Configuration;
+use std::{
+    collections::{hash_map::DefaultHasher, HashMap},
+    hash::{Hash, Hasher},
+    sync::{Arc, Mutex},
+};
 
-/// intended to be used with `wgpu::include_wgsl!("shader.wgsl")`
+use log::warn;
+
+use crate::{renderer::Configuration, tonemap::HdrTarget};
+
+/// Everything about a render pipeline except its shader source and layouts.
+/// <br>
+/// Starts out as an opaque, back face culled pipeline drawing to the [`HdrTarget`] with the
+/// configured depth test, the other methods change single aspects of it.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct PipelineDescriptor {
+    pub topology: wgpu::PrimitiveTopology,
+    pub front_face: wgpu::FrontFace,
+    pub cull_mode: Option<wgpu::Face>,
+    /// Anything but `Fill` needs a device feature, pipelines fall back to `Fill` without it
+    pub polygon_mode: wgpu::PolygonMode,
+    pub depth_stencil: Option<wgpu::DepthStencilState>,
+    pub sample_count: u32,
+    pub alpha_to_coverage: bool,
+    pub targets: Vec<Option<wgpu::ColorTargetState>>,
+    pub vertex_entry_point: String,
+    /// `None` for depth only pipelines without a fragment stage
+    pub fragment_entry_point: Option<String>,
+    /// Needs the `PUSH_CONSTANTS` device feature
+    pub push_constant_ranges: Vec<wgpu::PushConstantRange>,
+}
+
+impl PipelineDescriptor {
+    pub fn new(config: &Configuration, topology: wgpu::PrimitiveTopology) -> Self {
+        Self {
+            topology,
+            front_face: wgpu::FrontFace::Ccw,
+            cull_mode: Some(wgpu::Face::Back),
+            polygon_mode: wgpu::PolygonMode::Fill,
+            depth_stencil: Some(config.settings.depth.depth_stencil_state()),
+            sample_count: 1,
+            alpha_to_coverage: false,
+            targets: vec![Some(wgpu::ColorTargetState {
+                format: HdrTarget::FORMAT,
+                blend: Some(wgpu::BlendState::REPLACE),
+                write_mask: wgpu::ColorWrites::ALL,
+            })],
+            vertex_entry_point: "vs_main".into(),
+            fragment_entry_point: Some("fs_main".into()),
+            push_constant_ranges: Vec::new(),
+        }
+    }
+
+    /// Sets the blend state of every color target
+    pub fn blend(mut self, blend: Option<wgpu::BlendState>) -> Self {
+        for target in self.targets.iter_mut().flatten() {
+            target.blend = blend;
+        }
+        self
+    }
+
+    pub fn cull_mode(mut self, cull_mode: Option<wgpu::Face>) -> Self {
+        self.cull_mode = cull_mode;
+        self
+    }
+
+    /// Double sided geometry is not culled, like glTF materials with `doubleSided`
+    pub fn double_sided(self, double_sided: bool) -> Self {
+        self.cull_mode((!double_sided).then_some(wgpu::Face::Back))
+    }
+
+    pub fn front_face(mut self, front_face: wgpu::FrontFace) -> Self {
+        self.front_face = front_face;
+        self
+    }
+
+    /// `Line` draws wireframes
+    pub fn polygon_mode(mut self, polygon_mode: wgpu::PolygonMode) -> Self {
+        self.polygon_mode = polygon_mode;
+        self
+    }
+
+    pub fn depth_stencil(mut self, depth_stencil: Option<wgpu::DepthStencilState>) -> Self {
+        self.depth_stencil = depth_stencil;
+        self
+    }
+
+    /// Keeps the depth test, but doesn't write depth, like for transparent geometry
+    pub fn depth_write(mut self, enabled: bool) -> Self {
+        if let Some(depth_stencil) = &mut self.depth_stencil {
+            depth_stencil.depth_write_enabled = enabled;
+        }
+        self
+    }
+
+    pub fn multisample(mut self, sample_count: u32, alpha_to_coverage: bool) -> Self {
+        self.sample_count = sample_count;
+        self.alpha_to_coverage = alpha_to_coverage;
+        self
+    }
+
+    pub fn targets(mut self, targets: Vec<Option<wgpu::ColorTargetState>>) -> Self {
+        self.targets = targets;
+        self
+    }
+
+    /// Sets the entry points, a `fragment` of `None` leaves out the fragment stage
+    pub fn entry_points(mut self, vertex: &str, fragment: Option<&str>) -> Self {
+        self.vertex_entry_point = vertex.into();
+        self.fragment_entry_point = fragment.map(Into::into);
+        self
+    }
+
+    pub fn push_constants(mut self, ranges: Vec<wgpu::PushConstantRange>) -> Self {
+        self.push_constant_ranges = ranges;
+        self
+    }
+}
+
+/// Everything a pipeline is created from, pipelines with equal keys are interchangeable
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+struct PipelineKey {
+    /// Hash of the shader source
+    shader: u64,
+    vertex_buffers: Vec<VertexBufferKey>,
+    bind_group_layouts: Vec<wgpu::Id<wgpu::BindGroupLayout>>,
+    descriptor: PipelineDescriptor,
+}
+
+/// An owned [`wgpu::VertexBufferLayout`]
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+struct VertexBufferKey {
+    array_stride: wgpu::BufferAddress,
+    step_mode: wgpu::VertexStepMode,
+    attributes: Vec<wgpu::VertexAttribute>,
+}
+
+impl From<&wgpu::VertexBufferLayout<'_>> for VertexBufferKey {
+    fn from(layout: &wgpu::VertexBufferLayout) -> Self {
+        Self {
+            array_stride: layout.array_stride,
+            step_mode: layout.step_mode,
+            attributes: layout.attributes.to_vec(),
+        }
+    }
+}
+
+/// Shader modules and render pipelines, so that primitives drawn the same way share them
+#[derive(Default)]
+pub struct PipelineCache {
+    shaders: Mutex<HashMap<u64, Arc<wgpu::ShaderModule>>>,
+    pipelines: Mutex<HashMap<PipelineKey, Arc<wgpu::RenderPipeline>>>,
+}
+
+impl PipelineCache {
+    /// The number of distinct pipelines created so far
+    pub fn len(&self) -> usize {
+        self.pipelines
+            .lock()
+            .expect("pipeline cache is poisoned")
+            .len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn shader(
+        &self,
+        device: &wgpu::Device,
+        hash: u64,
+        source: &str,
+        label: Option<&str>,
+    ) -> Arc<wgpu::ShaderModule> {
+        let mut shaders = self.shaders.lock().expect("pipeline cache is poisoned");
+
+        shaders
+            .entry(hash)
+            .or_insert_with(|| {
+                Arc::new(device.create_shader_module(wgpu::ShaderModuleDescriptor {
+                    label,
+                    source: wgpu::ShaderSource::Wgsl(source.into()),
+                }))
+            })
+            .clone()
+    }
+}
+
+/// `source` is WGSL, intended to be used with `include_str!("shader.wgsl")`
+/// <br>
+/// Returns the cached pipeline if one was created with the same shader, layouts and descriptor
+/// before.
 pub fn create(
     config: &Configuration,
     vertex_buffer_layouts: &[wgpu::VertexBufferLayout],
     bind_group_layouts: &[&wgpu::BindGroupLayout],
-    description: wgpu::ShaderModuleDescriptor,
+    source: &str,
+    descriptor: &PipelineDescriptor,
     label: Option<&str>,
-) -> wgpu::RenderPipeline {
-    let shader = config.device.create_shader_module(description);
+) -> Arc<wgpu::RenderPipeline> {
+    let cache = &config.pipeline_cache;
+
+    let mut descriptor = descriptor.clone();
+    let polygon_feature = match descriptor.polygon_mode {
+        wgpu::PolygonMode::Fill => wgpu::Features::empty(),
+        wgpu::PolygonMode::Line => wgpu::Features::POLYGON_MODE_LINE,
+        wgpu::PolygonMode::Point => wgpu::Features::POLYGON_MODE_POINT,
+    };
+    if !config.device.features().contains(polygon_feature) {
+        warn!(
+            "{:?} polygons are not supported by the device, filling them instead",
+            descriptor.polygon_mode
+        );
+        descriptor.polygon_mode = wgpu::PolygonMode::Fill;
+    }
+
+    let key = PipelineKey {
+        shader: shader_hash(source),
+        vertex_buffers: vertex_buffer_layouts.iter().map(Into::into).collect(),
+        bind_group_layouts: bind_group_layouts
+            .iter()
+            .map(|layout| layout.global_id())
+            .collect(),
+        descriptor,
+    };
+    let descriptor = &key.descriptor;
+
+    if let Some(pipeline) = cache
+        .pipelines
+        .lock()
+        .expect("pipeline cache is poisoned")
+        .get(&key)
+    {
+        return pipeline.clone();
+    }
+
+    let shader = cache.shader(&config.device, key.shader, source, label);
 
     let layout = config
         .device
         .create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
             label,
             bind_group_layouts,
-            push_constant_ranges: &[],
+            push_constant_ranges: &descriptor.push_constant_ranges,
         });
 
     // modification of https://sotrh.github.io/learn-wgpu/beginner/tutorial3-pipeline/#how-do-we-use-the-shaders
@@ -26,43 +252,54 @@ pub fn create(
             layout: Some(&layout),
             vertex: wgpu::VertexState {
                 module: &shader,
-                entry_point: "vs_main",
+                entry_point: &descriptor.vertex_entry_point,
                 buffers: vertex_buffer_layouts,
                 // Additional compilation options
                 compilation_options: Default::default(),
             },
-            fragment: Some(wgpu::FragmentState {
-                module: &shader,
-                entry_point: "fs_main",
-                targets: &[Some(wgpu::ColorTargetState {
-                    format: config.surface_config.format,
-                    blend: Some(wgpu::BlendState::REPLACE),
-                    write_mask: wgpu::ColorWrites::ALL,
-                })],
-                // Additional compilation options
-                compilation_options: Default::default(),
+            fragment: descriptor.fragment_entry_point.as_ref().map(|entry_point| {
+                wgpu::FragmentState {
+                    module: &shader,
+                    entry_point,
+                    targets: &descriptor.targets,
+                    // Additional compilation options
+                    compilation_options: Default::default(),
+                }
             }),
             primitive: wgpu::PrimitiveState {
-                topology: wgpu::PrimitiveTopology::TriangleList,
+                topology: descriptor.topology,
                 strip_index_format: None,
-                front_face: wgpu::FrontFace::Ccw,
-                cull_mode: Some(wgpu::Face::Back),
+                front_face: descriptor.front_face,
+                cull_mode: descriptor.cull_mode,
 
-                // Setting this to anything other than Fill requires Features::NON_FILL_POLYGON_MODE
-                polygon_mode: wgpu::PolygonMode::Fill,
+                // Setting this to anything other than Fill requires Features::POLYGON_MODE_*
+                polygon_mode: descriptor.polygon_mode,
                 // Requires Features::DEPTH_CLIP_CONTROL
                 unclipped_depth: false,
                 // Requires Features::CONSERVATIVE_RASTERIZATION
                 conservative: false,
             },
-            depth_stencil: None,
+            depth_stencil: descriptor.depth_stencil.clone(),
             multisample: wgpu::MultisampleState {
-                count: 1,
+                count: descriptor.sample_count,
                 mask: !0,
-                alpha_to_coverage_enabled: false,
+                alpha_to_coverage_enabled: descriptor.alpha_to_coverage,
             },
             multiview: None,
         });
 
+    let pipeline = Arc::new(pipeline);
+    cache
+        .pipelines
+        .lock()
+        .expect("pipeline cache is poisoned")
+        .insert(key, pipeline.clone());
+
     pipeline
 }
+
+fn shader_hash(source: &str) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    source.hash(&mut hasher);
+    hasher.finish()
+}
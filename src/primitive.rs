@@ -1,54 +1,60 @@
+use std::sync::Arc;
+
+use getset::{CopyGetters, Getters};
+use gltf::material::AlphaMode;
+
 use crate::{
-    pipeline, renderer::Configuration, model_buffer_info::ModelBufferIndo, view::ViewType,
+    material::Material,
+    math::vec::Vec3,
+    model_buffer_info::ModelBufferIndo,
+    pipeline::{self, PipelineDescriptor},
+    renderer::Configuration,
+    vertex::{VertexInput, VertexLayout},
+    view::ViewInfo,
 };
 
+#[derive(Getters, CopyGetters)]
 pub struct Primitive {
-    pipeline: wgpu::RenderPipeline,
-    views: Vec<ViewType>,
+    /// Shared with all primitives drawn the same way
+    #[getset(get = "pub")]
+    pipeline: Arc<wgpu::RenderPipeline>,
+    /// Depth only pipeline of the shadow pass, `None` for blended primitives which cast no shadow
+    #[getset(get = "pub")]
+    shadow_pipeline: Option<Arc<wgpu::RenderPipeline>>,
+    vertex_layout: VertexLayout,
+    indices: Option<(ViewInfo, wgpu::IndexFormat)>,
+    /// Index into the materials of the graph
+    #[getset(get_copy = "pub")]
+    material: usize,
+    /// Center of the bounding box in local space, transparent primitives are sorted by it
+    #[getset(get_copy = "pub")]
+    center: Vec3,
+    /// Half the diagonal of the bounding box, the radius of a sphere around the primitive
+    #[getset(get_copy = "pub")]
+    radius: f32,
+    /// Number of vertices to draw if the primitive has no indices
+    vertex_count: u32,
 }
 
 impl Primitive {
+    /// `shadow_bind_group_layouts` are the groups of the shadow pass, the material is appended to
+    /// them for alpha tested primitives
     pub fn new(
         config: &Configuration,
         primitive: &gltf::Primitive,
         mesh: &gltf::Mesh,
         bind_group_layouts: &[&wgpu::BindGroupLayout],
+        shadow_bind_group_layouts: &[&wgpu::BindGroupLayout],
         buffer_info: &ModelBufferIndo,
+        materials: &[Material],
     ) -> Self {
-        let attributes = primitive.attributes();
-
-        let layouts: Vec<_> = attributes
-            .map(|(attr, _)| match attr {
-                gltf::Semantic::Positions => wgpu::VertexBufferLayout {
-                    step_mode: wgpu::VertexStepMode::Vertex,
-                    attributes: &[wgpu::VertexAttribute {
-                        format: wgpu::VertexFormat::Float32x3,
-                        offset: 0,
-                        shader_location: 0,
-                    }],
-                    array_stride: 12,
-                },
-                gltf::Semantic::Normals => wgpu::VertexBufferLayout {
-                    step_mode: wgpu::VertexStepMode::Vertex,
-                    attributes: &[wgpu::VertexAttribute {
-                        format: wgpu::VertexFormat::Float32x3,
-                        offset: 0,
-                        shader_location: 1,
-                    }],
-                    array_stride: 12,
-                },
-                gltf::Semantic::TexCoords(0) => wgpu::VertexBufferLayout {
-                    step_mode: wgpu::VertexStepMode::Vertex,
-                    attributes: &[wgpu::VertexAttribute {
-                        format: wgpu::VertexFormat::Float32x2,
-                        offset: 0,
-                        shader_location: 2,
-                    }],
-                    array_stride: 8,
-                },
-                _ => panic!("Vertex attribute {:?} not implemented!", attr),
-            })
-            .collect();
+        let vertex_layout = VertexLayout::new(&VertexInput::pbr(), primitive, buffer_info);
+
+        // the default material is the last one
+        let material = primitive.material().index().unwrap_or(materials.len() - 1);
+
+        let mut bind_group_layouts = bind_group_layouts.to_vec();
+        bind_group_layouts.push(&materials[material].bind_group().layout);
 
         let label = &format!(
             "pipeline for mesh '{}' with material '{}'",
@@ -56,58 +62,140 @@ impl Primitive {
             primitive.material().name().unwrap_or("unnamed material")
         );
 
+        let data = materials[material].data();
+        let mut descriptor = PipelineDescriptor::new(config, topology(primitive.mode()))
+            .double_sided(data.double_sided);
+        if data.alpha_mode == AlphaMode::Blend {
+            // transparent primitives are drawn back to front, so they don't need to write depth
+            descriptor = descriptor
+                .blend(Some(wgpu::BlendState::ALPHA_BLENDING))
+                .depth_write(false);
+        }
+
         let pipeline = pipeline::create(
-            &config,
-            &layouts,
-            bind_group_layouts,
-            wgpu::include_wgsl!("test.wgsl"),
-            Some(&label),
+            config,
+            &vertex_layout.buffer_layouts(),
+            &bind_group_layouts,
+            include_str!("pbr.wgsl"),
+            &descriptor,
+            Some(label),
         );
 
-        let mut views: Vec<_> = primitive
-            .attributes()
-            .map(|(_, attr)| buffer_info.views()[attr.index()])
-            .collect();
+        let shadow_pipeline = (data.alpha_mode != AlphaMode::Blend).then(|| {
+            // only alpha tested primitives need a fragment stage, to discard their holes
+            let masked = data.alpha_mode == AlphaMode::Mask;
 
-        views.push(
-            primitive
-                .indices()
-                .map(|indices| buffer_info.views()[indices.index()])
-                .expect("No indices accessor!"),
-        );
+            let mut bind_group_layouts = shadow_bind_group_layouts.to_vec();
+            if masked {
+                bind_group_layouts.push(&materials[material].bind_group().layout);
+            }
+
+            let descriptor = PipelineDescriptor::new(config, topology(primitive.mode()))
+                .double_sided(data.double_sided)
+                .depth_stencil(Some(config.settings.shadows.depth_stencil_state()))
+                .targets(Vec::new())
+                .entry_points("vs_main", masked.then_some("fs_mask"));
+
+            pipeline::create(
+                config,
+                &vertex_layout.buffer_layouts(),
+                &bind_group_layouts,
+                include_str!("shadow.wgsl"),
+                &descriptor,
+                Some("Shadow"),
+            )
+        });
+
+        let indices = buffer_info.indices(mesh, primitive);
 
-        Self { pipeline, views }
+        let vertex_count = primitive
+            .get(&gltf::Semantic::Positions)
+            .map(|positions| positions.count() as u32)
+            .unwrap_or_default();
+
+        let (min, max) = bounds(primitive).unwrap_or_default();
+
+        Self {
+            pipeline,
+            shadow_pipeline,
+            vertex_layout,
+            indices,
+            material,
+            center: (min + max) * 0.5,
+            radius: (max - min).length() * 0.5,
+            vertex_count,
+        }
     }
 
-    pub fn render<'a>(
+    /// Binds the vertex and index buffers and draws, the pipeline and bind groups have to be set
+    /// by the caller
+    pub fn draw<'a>(
         &'a self,
         render_pass: &mut wgpu::RenderPass<'a>,
         buffer_info: &'a ModelBufferIndo,
     ) {
-        render_pass.set_pipeline(&self.pipeline);
-
-        for (i, view) in self.views.iter().enumerate() {
-            match view {
-                ViewType::Vertex(info) => {
-                    render_pass.set_vertex_buffer(
-                        i as u32,
-                        buffer_info
-                            .vertex_buffer()
-                            .slice(info.offset..info.offset + info.lenght),
-                    );
-                }
-                ViewType::Index(info) => {
-                    render_pass.set_index_buffer(
-                        buffer_info
-                            .index_buffer()
-                            .slice(info.offset..info.offset + info.lenght),
-                        wgpu::IndexFormat::Uint16,
-                    );
-
-                    // Requiring that the index view is the last view (all vertex buffers have been set)
-                    render_pass.draw_indexed(0..info.lenght as u32 / 2, 0, 0..1);
-                }
+        // accessors may be empty, but wgpu can't bind empty buffer slices
+        let empty_indices = self.indices.is_some_and(|(info, _)| info.lenght == 0);
+        let empty_vertices = self.vertex_count == 0
+            || self
+                .vertex_layout
+                .slots()
+                .iter()
+                .any(|slot| slot.view.lenght == 0);
+        if empty_indices || empty_vertices {
+            return;
+        }
+
+        for (i, slot) in self.vertex_layout.slots().iter().enumerate() {
+            let info = slot.view;
+            render_pass.set_vertex_buffer(
+                i as u32,
+                buffer_info
+                    .vertex_buffer()
+                    .slice(info.offset..info.offset + info.lenght),
+            );
+        }
+
+        match self.indices {
+            Some((info, format)) => {
+                render_pass.set_index_buffer(
+                    buffer_info
+                        .index_buffer()
+                        .slice(info.offset..info.offset + info.lenght),
+                    format,
+                );
+                render_pass.draw_indexed(0..(info.lenght / info.stride) as u32, 0, 0..1);
             }
+            None => render_pass.draw(0..self.vertex_count, 0..1),
         }
     }
 }
+
+/// Line loops and triangle fans are converted to lists when uploading the indices
+fn topology(mode: gltf::mesh::Mode) -> wgpu::PrimitiveTopology {
+    match mode {
+        gltf::mesh::Mode::Points => wgpu::PrimitiveTopology::PointList,
+        gltf::mesh::Mode::Lines | gltf::mesh::Mode::LineLoop => wgpu::PrimitiveTopology::LineList,
+        gltf::mesh::Mode::LineStrip => wgpu::PrimitiveTopology::LineStrip,
+        gltf::mesh::Mode::Triangles | gltf::mesh::Mode::TriangleFan => {
+            wgpu::PrimitiveTopology::TriangleList
+        }
+        gltf::mesh::Mode::TriangleStrip => wgpu::PrimitiveTopology::TriangleStrip,
+    }
+}
+
+/// The `min` / `max` bounds of the positions, which glTF requires
+fn bounds(primitive: &gltf::Primitive) -> Option<(Vec3, Vec3)> {
+    let positions = primitive.get(&gltf::Semantic::Positions)?;
+
+    let bound = |value: gltf::json::Value| {
+        let values = value.as_array()?;
+        let component = |i: usize| Some(values.get(i)?.as_f64()? as f32);
+        Some(Vec3::new(component(0)?, component(1)?, component(2)?))
+    };
+
+    let min = bound(positions.min()?)?;
+    let max = bound(positions.max()?)?;
+
+    Some((min, max))
+}
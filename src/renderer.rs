@@ -1,48 +1,147 @@
-use winit::window::Window;
+use std::{fmt, path::Path};
 
-use log::info;
+use winit::{dpi::PhysicalSize, window::Window};
 
-use crate::graph::Graph;
+use log::{info, warn};
+
+use crate::{
+    bindgroup::LayoutCache,
+    depth::{DepthConfig, DepthTexture},
+    environment::{Environment, EnvironmentConfig},
+    graph::{Graph, GraphError},
+    offscreen::OffscreenTarget,
+    pipeline::PipelineCache,
+    shadow::ShadowConfig,
+    tonemap::{HdrTarget, Tonemap, TonemapConfig},
+};
+
+/// Options which have to be known before the scene is loaded, as pipelines depend on them
+#[derive(Clone, Debug, Default)]
+pub struct RenderSettings {
+    pub depth: DepthConfig,
+    pub shadows: ShadowConfig,
+    pub environment: EnvironmentConfig,
+    pub tonemap: TonemapConfig,
+    /// Lights scenes without lights of their own with a directional light from above, instead of
+    /// the environment only
+    pub fallback_light: bool,
+}
+
+/// Everything that can go wrong while setting up a renderer or capturing a frame
+#[derive(Debug)]
+pub enum RendererError {
+    /// Neither a GPU nor a software adapter is available
+    NoAdapter,
+    /// Frames can only be captured from headless renderers
+    NotHeadless,
+    RequestDevice(wgpu::RequestDeviceError),
+    Graph(GraphError),
+    /// The environment map could not be read or decoded
+    Environment(image::ImageError),
+    /// The rendered frame could not be read back from the GPU
+    Readback(wgpu::BufferAsyncError),
+    /// The captured frame could not be encoded or written
+    Image(image::ImageError),
+}
+
+impl fmt::Display for RendererError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RendererError::NoAdapter => write!(f, "no suitable graphics adapter found"),
+            RendererError::NotHeadless => write!(f, "only headless renderers can capture frames"),
+            RendererError::RequestDevice(err) => write!(f, "failed to request a device: {err}"),
+            RendererError::Graph(err) => write!(f, "failed to load the scene: {err}"),
+            RendererError::Environment(err) => write!(f, "failed to load the environment: {err}"),
+            RendererError::Readback(err) => write!(f, "failed to read the frame back: {err}"),
+            RendererError::Image(err) => write!(f, "failed to write the frame: {err}"),
+        }
+    }
+}
+
+impl std::error::Error for RendererError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            RendererError::NoAdapter | RendererError::NotHeadless => None,
+            RendererError::RequestDevice(err) => Some(err),
+            RendererError::Graph(err) => Some(err),
+            RendererError::Environment(err) => Some(err),
+            RendererError::Readback(err) => Some(err),
+            RendererError::Image(err) => Some(err),
+        }
+    }
+}
+
+impl From<GraphError> for RendererError {
+    fn from(err: GraphError) -> Self {
+        RendererError::Graph(err)
+    }
+}
+
+impl From<wgpu::RequestDeviceError> for RendererError {
+    fn from(err: wgpu::RequestDeviceError) -> Self {
+        RendererError::RequestDevice(err)
+    }
+}
+
+impl From<wgpu::BufferAsyncError> for RendererError {
+    fn from(err: wgpu::BufferAsyncError) -> Self {
+        RendererError::Readback(err)
+    }
+}
+
+impl From<image::ImageError> for RendererError {
+    fn from(err: image::ImageError) -> Self {
+        RendererError::Image(err)
+    }
+}
 
 pub struct Configuration<'a> {
-    pub surface: wgpu::Surface<'a>,
+    /// `None` when rendering headless
+    pub surface: Option<wgpu::Surface<'a>>,
     pub device: wgpu::Device,
     pub queue: wgpu::Queue,
+    /// Format and size of the color target, also kept up to date when rendering headless
     pub surface_config: wgpu::SurfaceConfiguration,
     pub size: winit::dpi::PhysicalSize<u32>,
+    pub settings: RenderSettings,
+    pub layout_cache: LayoutCache,
+    pub pipeline_cache: PipelineCache,
 
     // there are unsafe references so a reference here ensures a drop
     // according to https://sotrh.github.io/learn-wgpu/beginner/tutorial2-surface/#first-some-housekeeping-state
-    pub window: &'a Window,
+    /// `None` when rendering headless
+    pub window: Option<&'a Window>,
 }
 
-/// A renderer, assuming that there is a surface to draw on
+/// A renderer drawing either to the surface of a window or to an offscreen target
 pub struct RendererState<'a> {
     pub config: Configuration<'a>,
 
     pub graph: Graph,
+    /// The environment map lighting the scene and drawn behind it
+    pub environment: Environment,
+    pub depth_texture: DepthTexture,
+    /// The color target of the scene, tonemapped onto the surface
+    pub hdr_target: HdrTarget,
+    pub tonemap: Tonemap,
+    /// The color target of headless renderers
+    pub offscreen: Option<OffscreenTarget>,
 }
 
 impl<'a> RendererState<'a> {
-    pub async fn new(window: &'a Window) -> Self {
+    /// Creates a renderer drawing the glTF file at `scene`, or the bundled scene if there is none
+    pub async fn new(
+        window: &'a Window,
+        scene: Option<&Path>,
+        settings: RenderSettings,
+    ) -> Result<Self, RendererError> {
         let size = window.inner_size();
 
         let instance = wgpu::Instance::new(wgpu::InstanceDescriptor::default());
 
         let surface = instance.create_surface(window).unwrap();
 
-        let adapter = instance
-            .request_adapter(&wgpu::RequestAdapterOptionsBase {
-                compatible_surface: Some(&surface),
-                ..Default::default()
-            })
-            .await
-            .unwrap();
-
-        let (device, queue) = adapter
-            .request_device(&wgpu::DeviceDescriptor::default(), None)
-            .await
-            .unwrap();
+        let (adapter, device, queue) = request_device(&instance, Some(&surface)).await?;
 
         let surface_caps = surface.get_capabilities(&adapter);
 
@@ -66,21 +165,89 @@ impl<'a> RendererState<'a> {
         };
 
         let config = Configuration {
-            window,
-            surface,
+            window: Some(window),
+            surface: Some(surface),
             device,
             queue,
             surface_config: config,
             size,
+            settings,
+            layout_cache: LayoutCache::default(),
+            pipeline_cache: PipelineCache::default(),
+        };
+
+        Self::with_config(config, scene, None)
+    }
+
+    /// Creates a renderer without a window, drawing `size` pixel frames into an offscreen target
+    /// which can be read back with [`RendererState::capture`].
+    /// <br>
+    /// Falls back to a software adapter if there is no GPU, so this also works on servers and CI.
+    pub async fn headless(
+        size: PhysicalSize<u32>,
+        scene: Option<&Path>,
+        settings: RenderSettings,
+    ) -> Result<RendererState<'static>, RendererError> {
+        let instance = wgpu::Instance::new(wgpu::InstanceDescriptor::default());
+
+        let (_, device, queue) = request_device(&instance, None).await?;
+
+        let config = Configuration {
+            window: None,
+            surface: None,
+            device,
+            queue,
+            surface_config: wgpu::SurfaceConfiguration {
+                usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+                format: OffscreenTarget::FORMAT,
+                width: size.width,
+                height: size.height,
+                present_mode: wgpu::PresentMode::Fifo,
+                alpha_mode: wgpu::CompositeAlphaMode::Opaque,
+                view_formats: vec![],
+                desired_maximum_frame_latency: 2,
+            },
+            size,
+            settings,
+            layout_cache: LayoutCache::default(),
+            pipeline_cache: PipelineCache::default(),
         };
 
-        let graph = Graph::create(&config);
+        let offscreen = OffscreenTarget::new(&config);
 
-        Self { config, graph }
+        RendererState::with_config(config, scene, Some(offscreen))
     }
 
-    pub fn window(&self) -> &Window {
-        &self.config.window
+    fn with_config(
+        config: Configuration<'a>,
+        scene: Option<&Path>,
+        offscreen: Option<OffscreenTarget>,
+    ) -> Result<Self, RendererError> {
+        let environment = Environment::new(&config).map_err(RendererError::Environment)?;
+
+        let graph = match scene {
+            Some(path) => Graph::load(&config, path, &environment)?,
+            None => Graph::create(&config, &environment)?,
+        };
+
+        let depth_texture = DepthTexture::new(&config);
+        let hdr_target = HdrTarget::new(&config);
+        let tonemap = Tonemap::new(&config, &hdr_target);
+
+        Ok(Self {
+            config,
+            graph,
+            environment,
+            depth_texture,
+            hdr_target,
+            tonemap,
+            offscreen,
+        })
+    }
+
+    /// The window drawn to, `None` when rendering headless
+    pub fn window(&self) -> Option<&Window> {
+        self.config.window
     }
 
     // copy of https://sotrh.github.io/learn-wgpu/beginner/tutorial2-surface/#resize
@@ -96,57 +263,186 @@ impl<'a> RendererState<'a> {
             config.size = new_size;
             config.surface_config.width = new_size.width;
             config.surface_config.height = new_size.height;
-            config
-                .surface
-                .configure(&config.device, &config.surface_config);
+            if let Some(surface) = &config.surface {
+                surface.configure(&config.device, &config.surface_config);
+            }
+            if self.offscreen.is_some() {
+                self.offscreen = Some(OffscreenTarget::new(config));
+            }
+
+            self.depth_texture = DepthTexture::new(config);
+            self.hdr_target = HdrTarget::new(config);
+            self.tonemap.resize(config, &self.hdr_target);
+            self.graph.camera_mut().resize(&config.queue, new_size);
         }
     }
 
+    /// Draws a frame to the surface of the window
     pub fn draw(&mut self) -> Result<(), wgpu::SurfaceError> {
-        let config = &mut self.config;
+        let Some(surface) = &self.config.surface else {
+            // headless renderers draw with `capture`
+            return Ok(());
+        };
 
-        let output = config.surface.get_current_texture()?;
+        let output = surface.get_current_texture()?;
         let view = output
             .texture
             .create_view(&wgpu::TextureViewDescriptor::default());
 
+        self.render(&view);
+        output.present();
+
+        Ok(())
+    }
+
+    /// Draws a frame to the offscreen target of a headless renderer and reads it back
+    pub fn capture(&mut self) -> Result<image::RgbaImage, RendererError> {
+        // taken for the duration of `render`, which borrows all of `self`
+        let offscreen = self.offscreen.take().ok_or(RendererError::NotHeadless)?;
+
+        self.render(offscreen.view());
+        let frame = offscreen.read(&self.config);
+
+        // put back before a failed readback returns, so that later captures still work
+        self.offscreen = Some(offscreen);
+        Ok(frame?)
+    }
+
+    /// Captures a frame and writes it to a PNG file at `path`
+    pub fn save_png(&mut self, path: impl AsRef<Path>) -> Result<(), RendererError> {
+        let frame = self.capture()?;
+        frame.save_with_format(path, image::ImageFormat::Png)?;
+
+        Ok(())
+    }
+
+    /// Draws the graph into the [`HdrTarget`] and tonemaps it into `view`, which has the format of
+    /// [`Configuration::surface_config`]
+    pub fn render(&mut self, view: &wgpu::TextureView) {
+        self.graph.update(&self.config);
+
+        let config = &self.config;
+
         let mut encoder = config
             .device
             .create_command_encoder(&wgpu::CommandEncoderDescriptor {
                 label: Some("Render Encoder"),
             });
 
+        for (layer, view) in self.graph.lights().shadows().layers().iter().enumerate() {
+            let mut shadow_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Shadow Pass"),
+                color_attachments: &[],
+                depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                    view,
+                    depth_ops: Some(wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(1.0),
+                        store: wgpu::StoreOp::Store,
+                    }),
+                    stencil_ops: None,
+                }),
+                ..Default::default()
+            });
+
+            self.graph.render_shadows(&mut shadow_pass, layer);
+        }
+
+        let shadows = self.graph.lights().shadows();
+        if !shadows.tiles().is_empty() {
+            let mut atlas_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Shadow Atlas Pass"),
+                color_attachments: &[],
+                depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                    view: shadows.atlas_view(),
+                    depth_ops: Some(wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(1.0),
+                        store: wgpu::StoreOp::Store,
+                    }),
+                    stencil_ops: None,
+                }),
+                ..Default::default()
+            });
+
+            for (i, tile) in shadows.tiles().iter().enumerate() {
+                let (x, y, size) = (tile.x as f32, tile.y as f32, tile.size as f32);
+                atlas_pass.set_viewport(x, y, size, size, 0.0, 1.0);
+                self.graph
+                    .render_shadows(&mut atlas_pass, shadows.tile_view(i));
+            }
+        }
+
         let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
             label: Some("Color Pass"),
             color_attachments: &[Some(wgpu::RenderPassColorAttachment {
-                view: &view,
+                view: self.hdr_target.view(),
                 resolve_target: None,
                 ops: wgpu::Operations {
-                    load: wgpu::LoadOp::Clear(wgpu::Color {
-                        r: 0.8,
-                        g: 0.2,
-                        b: 0.3,
-                        a: 1.0,
-                    }),
+                    load: wgpu::LoadOp::Clear(config.settings.environment.background),
                     store: wgpu::StoreOp::Store,
                 },
             })],
-            depth_stencil_attachment: None, // Depth for later
+            depth_stencil_attachment: Some(self.depth_texture.attachment(config)),
             ..Default::default()
         });
 
         render_pass.set_bind_group(1, &self.graph.camera().bind_group().group, &[]);
+        self.environment.render_skybox(&mut render_pass);
+        render_pass.set_bind_group(2, &self.graph.lights().bind_group().group, &[]);
 
-        for primitive in self.graph.meshes() {
-            primitive.render(&mut render_pass, &self.graph.buffer_info());
-        }
+        self.graph.render(&mut render_pass);
 
         drop(render_pass);
 
+        self.tonemap.render(config, &mut encoder, view);
+
         // Has to be an iterator, hence once
         config.queue.submit(std::iter::once(encoder.finish()));
-        output.present();
-
-        Ok(())
     }
 }
+
+/// Picks a hardware adapter able to present to `surface`, falling back to a software adapter
+async fn request_device(
+    instance: &wgpu::Instance,
+    surface: Option<&wgpu::Surface<'_>>,
+) -> Result<(wgpu::Adapter, wgpu::Device, wgpu::Queue), RendererError> {
+    let options = |force_fallback_adapter| wgpu::RequestAdapterOptions {
+        compatible_surface: surface,
+        force_fallback_adapter,
+        ..Default::default()
+    };
+
+    let adapter = match instance.request_adapter(&options(false)).await {
+        Some(adapter) => adapter,
+        None => {
+            warn!("No hardware adapter found, falling back to a software adapter");
+            instance
+                .request_adapter(&options(true))
+                .await
+                .ok_or(RendererError::NoAdapter)?
+        }
+    };
+
+    info!("Using adapter {:?}", adapter.get_info());
+
+    // optional features pipelines can make use of if the adapter has them
+    let features = adapter.features()
+        & (wgpu::Features::POLYGON_MODE_LINE
+            | wgpu::Features::POLYGON_MODE_POINT
+            | wgpu::Features::PUSH_CONSTANTS);
+
+    let (device, queue) = adapter
+        .request_device(
+            &wgpu::DeviceDescriptor {
+                required_features: features,
+                required_limits: wgpu::Limits {
+                    max_push_constant_size: adapter.limits().max_push_constant_size,
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+            None,
+        )
+        .await?;
+
+    Ok((adapter, device, queue))
+}
@@ -0,0 +1,649 @@
+use getset::{CopyGetters, Getters};
+
+use crate::{
+    bindgroup::{create_bindgroup, BindGroupEntryInfo, BindGroupInfo},
+    camera::{Camera, Projection},
+    math::{
+        mat::Mat4,
+        vec::{Vec3, Vec4},
+    },
+    renderer::Configuration,
+    uniform_buffer::create_uniform_buffer,
+};
+
+/// Most cascades a directional light can be split into
+pub const MAX_CASCADES: u32 = 4;
+
+/// Most directional lights casting shadows at the same time, further lights are unshadowed
+pub const MAX_DIRECTIONAL_SHADOWS: usize = 4;
+
+/// Size of `ShadowView` in `shadow.wgsl`
+const SHADOW_VIEW_SIZE: u64 = 64;
+
+/// Size of `LocalShadow` in `pbr.wgsl`
+const LOCAL_SHADOW_SIZE: u64 = 96;
+
+/// Distance of the near plane of point and spot light shadows
+const LOCAL_NEAR: f32 = 0.05;
+
+/// How lights cast shadows
+#[derive(Clone, Copy, Debug)]
+pub struct ShadowConfig {
+    /// Number of cascades the view frustum is split into, between 1 and [`MAX_CASCADES`]
+    pub cascades: u32,
+    /// Width and height of every cascade in texels
+    pub resolution: u32,
+    /// Shadows end at this view distance, or at the far plane of the camera if it is closer.
+    /// <br>
+    /// Also the reach of the shadows of point and spot lights without a range.
+    pub max_distance: f32,
+    /// Blends the split distances between uniform at 0 and logarithmic at 1
+    pub split_lambda: f32,
+    /// Constant depth bias of the shadow pass, in units of the depth format
+    pub depth_bias: i32,
+    /// Depth bias of the shadow pass scaled by the slope of the caster
+    pub slope_bias: f32,
+    /// Receivers are moved along their normal by this many texels of their cascade
+    pub normal_bias: f32,
+    /// Radius of the PCF kernel in texels, 0 takes a single hardware filtered sample
+    pub pcf_radius: u32,
+    /// Tints everything by the cascade it is shadowed by, to tune the splits
+    pub debug_cascades: bool,
+    /// Width and height of the atlas the shadows of point and spot lights are allocated from
+    pub atlas_resolution: u32,
+    /// Width and height of an atlas tile, a spot light takes one, a point light one per cube face
+    pub tile_resolution: u32,
+    /// Most atlas tiles rendered per frame, the lights closest to the camera are served first and
+    /// the others stay unshadowed
+    pub tile_budget: u32,
+}
+
+impl Default for ShadowConfig {
+    fn default() -> Self {
+        Self {
+            cascades: 4,
+            resolution: 2048,
+            max_distance: 50.0,
+            split_lambda: 0.75,
+            depth_bias: 2,
+            slope_bias: 2.0,
+            normal_bias: 1.0,
+            pcf_radius: 1,
+            debug_cascades: false,
+            atlas_resolution: 4096,
+            tile_resolution: 512,
+            tile_budget: 24,
+        }
+    }
+}
+
+impl ShadowConfig {
+    /// The cascade count clamped to what the shaders support
+    pub fn cascade_count(&self) -> u32 {
+        self.cascades.clamp(1, MAX_CASCADES)
+    }
+
+    /// Depth state of the shadow pass, shadow maps always use a regular depth range
+    pub fn depth_stencil_state(&self) -> wgpu::DepthStencilState {
+        wgpu::DepthStencilState {
+            format: ShadowMaps::FORMAT,
+            depth_write_enabled: true,
+            depth_compare: wgpu::CompareFunction::Less,
+            stencil: Default::default(),
+            bias: wgpu::DepthBiasState {
+                constant: self.depth_bias,
+                slope_scale: self.slope_bias,
+                clamp: 0.0,
+            },
+        }
+    }
+}
+
+/// A point or spot light casting a shadow, see [`ShadowMaps::update`]
+#[derive(Clone, Copy, Debug)]
+pub enum LocalCaster {
+    /// Rendered into one tile per cube face, in the order +x, -x, +y, -y, +z, -z
+    Point { position: Vec3, range: f32 },
+    /// Rendered into one tile with a perspective projection around the cone
+    Spot {
+        position: Vec3,
+        direction: Vec3,
+        outer_cone_angle: f32,
+        range: f32,
+    },
+}
+
+impl LocalCaster {
+    /// The number of atlas tiles the light takes
+    pub fn tile_count(&self) -> usize {
+        match self {
+            LocalCaster::Point { .. } => 6,
+            LocalCaster::Spot { .. } => 1,
+        }
+    }
+
+    /// The view projection of every tile, with the field of view it was created with
+    fn view_projections(&self) -> Vec<(Mat4, f32)> {
+        match *self {
+            LocalCaster::Point { position, range } => {
+                let fov = std::f32::consts::FRAC_PI_2;
+                let projection = Mat4::perspective(fov, 1.0, LOCAL_NEAR, range);
+
+                CUBE_FACES
+                    .iter()
+                    .map(|&(direction, up)| {
+                        let view = Mat4::look_at(position, position + direction, up);
+                        (projection * view, fov)
+                    })
+                    .collect()
+            }
+            LocalCaster::Spot {
+                position,
+                direction,
+                outer_cone_angle,
+                range,
+            } => {
+                // a little wider than the cone, so that PCF at its edge stays inside the tile
+                let fov = (2.0 * outer_cone_angle * 1.1).min(3.0);
+                let projection = Mat4::perspective(fov, 1.0, LOCAL_NEAR, range);
+                let view = Mat4::look_at(position, position + direction, any_up(direction));
+
+                vec![(projection * view, fov)]
+            }
+        }
+    }
+}
+
+/// Direction and up vector of the cube faces, in the order `cube_face` in `pbr.wgsl` picks them
+const CUBE_FACES: [(Vec3, Vec3); 6] = [
+    (Vec3::new(1.0, 0.0, 0.0), Vec3::new(0.0, -1.0, 0.0)),
+    (Vec3::new(-1.0, 0.0, 0.0), Vec3::new(0.0, -1.0, 0.0)),
+    (Vec3::new(0.0, 1.0, 0.0), Vec3::new(0.0, 0.0, 1.0)),
+    (Vec3::new(0.0, -1.0, 0.0), Vec3::new(0.0, 0.0, -1.0)),
+    (Vec3::new(0.0, 0.0, 1.0), Vec3::new(0.0, -1.0, 0.0)),
+    (Vec3::new(0.0, 0.0, -1.0), Vec3::new(0.0, -1.0, 0.0)),
+];
+
+/// A square of the shadow atlas, in texels
+#[derive(Clone, Copy, Debug)]
+pub struct AtlasTile {
+    pub x: u32,
+    pub y: u32,
+    pub size: u32,
+}
+
+/// The shadow maps of all lights.
+/// <br>
+/// Every shadow casting directional light has [`ShadowConfig::cascades`] consecutive layers of
+/// one depth texture array. Point and spot lights get tiles of the atlas every frame, as long as
+/// the [`ShadowConfig::tile_budget`] lasts.
+/// <br>
+/// The shadow pass selects the view of a cascade or tile with a dynamic offset into
+/// [`ShadowMaps::bind_group`], the material shader samples all of them through the light group.
+#[derive(Getters, CopyGetters)]
+pub struct ShadowMaps {
+    #[getset(get = "pub")]
+    texture: wgpu::Texture,
+    /// All layers, for sampling
+    #[getset(get = "pub")]
+    view: wgpu::TextureView,
+    /// One view per layer, to render into
+    #[getset(get = "pub")]
+    layers: Vec<wgpu::TextureView>,
+    #[getset(get = "pub")]
+    sampler: wgpu::Sampler,
+    /// The matrices, splits and filter settings read by `pbr.wgsl`
+    #[getset(get = "pub")]
+    uniform: wgpu::Buffer,
+    /// The view projection of every layer followed by every tile, in slots `stride` apart
+    views: wgpu::Buffer,
+    #[getset(get = "pub")]
+    bind_group: BindGroupInfo,
+    #[getset(get_copy = "pub")]
+    stride: u64,
+    /// Number of directional lights with cascades
+    #[getset(get_copy = "pub")]
+    casters: usize,
+    #[getset(get = "pub")]
+    atlas: wgpu::Texture,
+    #[getset(get = "pub")]
+    atlas_view: wgpu::TextureView,
+    /// The view projection and atlas rectangle of every tile, read by `pbr.wgsl`
+    #[getset(get = "pub")]
+    local: wgpu::Buffer,
+    /// Number of tiles which can be rendered per frame
+    #[getset(get_copy = "pub")]
+    tile_count: usize,
+    /// Width and height of a tile, [`ShadowConfig::tile_resolution`] within the device limits
+    #[getset(get_copy = "pub")]
+    tile_resolution: u32,
+    /// Number of tiles in a row of the atlas, as it was created within the device limits
+    #[getset(get_copy = "pub")]
+    tiles_per_row: u32,
+    /// The tiles allocated by the last [`ShadowMaps::update`]
+    #[getset(get = "pub")]
+    tiles: Vec<AtlasTile>,
+}
+
+impl ShadowMaps {
+    pub const FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Depth32Float;
+
+    /// Creates cascades for `casters` directional lights, and the atlas if there are
+    /// `local_casters` point or spot lights
+    pub fn new(config: &Configuration, casters: usize, local_casters: usize) -> Self {
+        let settings = &config.settings.shadows;
+        let resolution = settings
+            .resolution
+            .clamp(1, config.device.limits().max_texture_dimension_2d);
+        let layer_count = (casters as u32 * settings.cascade_count()).max(1);
+
+        let texture = config.device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Shadow maps"),
+            size: wgpu::Extent3d {
+                width: resolution,
+                height: resolution,
+                depth_or_array_layers: layer_count,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: Self::FORMAT,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+            view_formats: &[],
+        });
+
+        // a single layer would be viewed as a plain 2D texture otherwise
+        let view = texture.create_view(&wgpu::TextureViewDescriptor {
+            dimension: Some(wgpu::TextureViewDimension::D2Array),
+            ..Default::default()
+        });
+
+        let layers = (0..casters as u32 * settings.cascade_count())
+            .map(|layer| {
+                texture.create_view(&wgpu::TextureViewDescriptor {
+                    label: Some("Shadow map layer"),
+                    dimension: Some(wgpu::TextureViewDimension::D2),
+                    base_array_layer: layer,
+                    array_layer_count: Some(1),
+                    ..Default::default()
+                })
+            })
+            .collect();
+
+        let sampler = config.device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("Shadow sampler"),
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            compare: Some(wgpu::CompareFunction::LessEqual),
+            ..Default::default()
+        });
+
+        let uniform = create_uniform_buffer(
+            config,
+            "Shadow uniform",
+            &ShadowUniform::default().bytes(settings),
+        );
+
+        let max_dimension = config.device.limits().max_texture_dimension_2d;
+        let tile_resolution = settings.tile_resolution.clamp(1, max_dimension);
+        let (atlas_resolution, tiles_per_row, tile_count) = if local_casters > 0 {
+            let requested = (settings.atlas_resolution / tile_resolution).max(1);
+            let atlas_resolution = requested.saturating_mul(tile_resolution).min(max_dimension);
+            // the clamped atlas holds fewer tiles than requested
+            let tiles_per_row = atlas_resolution / tile_resolution;
+            let tiles = tiles_per_row.pow(2);
+            (
+                atlas_resolution,
+                tiles_per_row,
+                settings.tile_budget.min(tiles) as usize,
+            )
+        } else {
+            // the atlas is bound either way
+            (1, 1, 0)
+        };
+
+        let atlas = config.device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Shadow atlas"),
+            size: wgpu::Extent3d {
+                width: atlas_resolution,
+                height: atlas_resolution,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: Self::FORMAT,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+            view_formats: &[],
+        });
+        let atlas_view = atlas.create_view(&wgpu::TextureViewDescriptor::default());
+
+        let local = config.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Local shadows"),
+            size: LOCAL_SHADOW_SIZE * tile_count.max(1) as u64,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let alignment = config.device.limits().min_uniform_buffer_offset_alignment as u64;
+        let stride = SHADOW_VIEW_SIZE.next_multiple_of(alignment);
+
+        let views = config.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Shadow views"),
+            size: stride * (layer_count as u64 + tile_count as u64),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let bind_group = create_bindgroup(
+            config,
+            &[BindGroupEntryInfo::dynamic(
+                wgpu::ShaderStages::VERTEX,
+                &views,
+                wgpu::BufferSize::new(SHADOW_VIEW_SIZE).expect("views aren't empty"),
+            )],
+            "Shadow views",
+        );
+
+        Self {
+            texture,
+            view,
+            layers,
+            sampler,
+            uniform,
+            views,
+            bind_group,
+            stride,
+            casters,
+            atlas,
+            atlas_view,
+            local,
+            tile_count,
+            tile_resolution,
+            tiles_per_row,
+            tiles: Vec::new(),
+        }
+    }
+
+    /// The entries of the light group sampling the shadow maps, starting at binding 1
+    pub fn entries(&self) -> [BindGroupEntryInfo<'_>; 5] {
+        [
+            BindGroupEntryInfo::new(
+                wgpu::ShaderStages::FRAGMENT,
+                self.uniform.as_entire_buffer_binding(),
+            ),
+            BindGroupEntryInfo::new(wgpu::ShaderStages::FRAGMENT, &self.view).texture(
+                wgpu::TextureViewDimension::D2Array,
+                wgpu::TextureSampleType::Depth,
+            ),
+            BindGroupEntryInfo::new(wgpu::ShaderStages::FRAGMENT, &self.sampler)
+                .sampler(wgpu::SamplerBindingType::Comparison),
+            BindGroupEntryInfo::new(
+                wgpu::ShaderStages::FRAGMENT,
+                self.local.as_entire_buffer_binding(),
+            )
+            .storage(true),
+            BindGroupEntryInfo::new(wgpu::ShaderStages::FRAGMENT, &self.atlas_view).texture(
+                wgpu::TextureViewDimension::D2,
+                wgpu::TextureSampleType::Depth,
+            ),
+        ]
+    }
+
+    /// The dynamic offset selecting the view projection of `view`, a cascade layer or a
+    /// [`ShadowMaps::tile_view`]
+    pub fn offset(&self, view: usize) -> u32 {
+        (view as u64 * self.stride) as u32
+    }
+
+    /// The view of the `i`th allocated tile, the views of the cascades come first
+    pub fn tile_view(&self, i: usize) -> usize {
+        self.layers.len() + i
+    }
+
+    /// Fits the cascades of every directional caster to the view frustum of `camera` and
+    /// allocates the atlas tiles of the point and spot lights.
+    /// <br>
+    /// `directions` are the world space directions of the directional casters, `bounds` is a
+    /// sphere around everything that can cast a shadow, so that casters between the light and a
+    /// cascade are not clipped. `locals` have to fit into [`ShadowMaps::tile_count`] tiles, their
+    /// tiles are allocated in order.
+    pub fn update(
+        &mut self,
+        config: &Configuration,
+        camera: &Camera,
+        directions: &[Vec3],
+        locals: &[LocalCaster],
+        bounds: (Vec3, f32),
+    ) {
+        let settings = &config.settings.shadows;
+        let resolution = self.texture.width() as f32;
+
+        let data = camera.data();
+        let far = data.far.min(settings.max_distance).max(data.near);
+        let splits = split_distances(data.near, far, settings);
+        let camera_world = data.view().inverse().unwrap_or_default();
+
+        let mut uniform = ShadowUniform::default();
+        let mut views = Vec::new();
+
+        for (i, &far) in splits.iter().enumerate() {
+            let near = if i == 0 { data.near } else { splits[i - 1] };
+            let corners = slice_corners(camera, near, far).map(|c| camera_world.transform_point(c));
+
+            let center = corners.iter().fold(Vec3::default(), |sum, &c| sum + c) * 0.125;
+            let radius = corners
+                .iter()
+                .map(|&c| (c - center).length())
+                .fold(0.0, f32::max);
+            // a radius independent of the camera rotation keeps the texel size and shadow edges
+            // from changing while looking around
+            let radius = (radius * 16.0).ceil() / 16.0;
+
+            uniform.splits[i] = far;
+            uniform.texel_sizes[i] = 2.0 * radius / resolution;
+
+            for (light, &direction) in directions.iter().enumerate() {
+                let layer = light * splits.len() + i;
+                uniform.view_projections[layer] =
+                    cascade_view_projection(direction, center, radius, bounds, resolution);
+            }
+        }
+        uniform.cascade_count = splits.len() as u32;
+
+        let mut view_projections = uniform.view_projections[..self.layers.len()].to_vec();
+
+        let per_row = self.tiles_per_row;
+        let atlas_resolution = self.atlas.width() as f32;
+        let mut local = Vec::new();
+        self.tiles.clear();
+
+        for (view_projection, fov) in locals.iter().flat_map(LocalCaster::view_projections) {
+            let i = self.tiles.len() as u32;
+            let size = self.tile_resolution;
+            let tile = AtlasTile {
+                x: (i % per_row) * size,
+                y: (i / per_row) * size,
+                size,
+            };
+            self.tiles.push(tile);
+            view_projections.push(view_projection);
+
+            let rect = Vec4::new(tile.x as f32, tile.y as f32, size as f32, size as f32)
+                * (1.0 / atlas_resolution);
+            let texel_scale = 2.0 * (fov / 2.0).tan() / size as f32;
+
+            local.extend(view_projection.bytes());
+            local.extend(rect.bytes());
+            local.extend(Vec4::new(texel_scale, 0.0, 0.0, 0.0).bytes());
+        }
+        debug_assert!(
+            self.tiles.len() <= self.tile_count,
+            "too many tiles allocated"
+        );
+
+        for view_projection in view_projections {
+            views.extend(view_projection.bytes());
+            views.resize(views.len() + (self.stride - SHADOW_VIEW_SIZE) as usize, 0);
+        }
+
+        config
+            .queue
+            .write_buffer(&self.uniform, 0, &uniform.bytes(settings));
+        if !views.is_empty() {
+            config.queue.write_buffer(&self.views, 0, &views);
+        }
+        if !local.is_empty() {
+            config.queue.write_buffer(&self.local, 0, &local);
+        }
+    }
+}
+
+/// Matches `ShadowUniform` in `pbr.wgsl`
+struct ShadowUniform {
+    view_projections: [Mat4; MAX_DIRECTIONAL_SHADOWS * MAX_CASCADES as usize],
+    /// View depth where every cascade ends
+    splits: [f32; MAX_CASCADES as usize],
+    /// World space size of a texel of every cascade
+    texel_sizes: [f32; MAX_CASCADES as usize],
+    cascade_count: u32,
+}
+
+impl Default for ShadowUniform {
+    fn default() -> Self {
+        Self {
+            view_projections: [Mat4::default(); MAX_DIRECTIONAL_SHADOWS * MAX_CASCADES as usize],
+            splits: [0.0; MAX_CASCADES as usize],
+            texel_sizes: [0.0; MAX_CASCADES as usize],
+            cascade_count: 0,
+        }
+    }
+}
+
+impl ShadowUniform {
+    fn bytes(&self, settings: &ShadowConfig) -> Vec<u8> {
+        let mut bytes: Vec<u8> = self
+            .view_projections
+            .iter()
+            .flat_map(|view_projection| view_projection.bytes())
+            .collect();
+        bytes.extend(Vec4::from(self.splits).bytes());
+        bytes.extend(Vec4::from(self.texel_sizes).bytes());
+        for value in [
+            self.cascade_count,
+            settings.pcf_radius,
+            settings.normal_bias.to_bits(),
+            settings.debug_cascades as u32,
+        ] {
+            bytes.extend(value.to_le_bytes());
+        }
+        bytes
+    }
+}
+
+/// The view depths the cascades end at, between a uniform and a logarithmic distribution
+/// <br>
+/// https://developer.nvidia.com/gpugems/gpugems3/part-ii-light-and-shadows/chapter-10-parallel-split-shadow-maps-programmable-gpus
+fn split_distances(near: f32, far: f32, settings: &ShadowConfig) -> Vec<f32> {
+    let count = settings.cascade_count();
+    let lambda = settings.split_lambda.clamp(0.0, 1.0);
+    // orthographic cameras may have their near plane at 0, where the logarithm is undefined
+    let log_near = near.max(far * 1e-4).max(1e-6);
+
+    (1..=count)
+        .map(|i| {
+            let fraction = i as f32 / count as f32;
+            let logarithmic = log_near * (far / log_near).powf(fraction);
+            let uniform = near + (far - near) * fraction;
+            lambda * logarithmic + (1.0 - lambda) * uniform
+        })
+        .collect()
+}
+
+/// The corners of the part of the view frustum between the view depths `near` and `far`, in view
+/// space
+fn slice_corners(camera: &Camera, near: f32, far: f32) -> [Vec3; 8] {
+    let aspect = camera.aspect();
+    let extent = |depth: f32| match camera.data().projection {
+        Projection::Perspective { fov } => {
+            let y = depth * (fov / 2.0).tan();
+            (y * aspect, y)
+        }
+        Projection::Orthographic { ymag, .. } => (ymag * aspect, ymag),
+    };
+
+    let mut corners = [Vec3::default(); 8];
+    for (i, depth) in [near, far].into_iter().enumerate() {
+        let (x, y) = extent(depth);
+        corners[i * 4] = Vec3::new(-x, -y, -depth);
+        corners[i * 4 + 1] = Vec3::new(x, -y, -depth);
+        corners[i * 4 + 2] = Vec3::new(x, y, -depth);
+        corners[i * 4 + 3] = Vec3::new(-x, y, -depth);
+    }
+    corners
+}
+
+/// An orthographic projection looking along `direction` at the sphere around a cascade.
+/// <br>
+/// The center is snapped to whole texels, so that shadow edges don't shimmer when the camera
+/// moves, and the near plane is pulled back to the scene `bounds` to keep casters outside the
+/// cascade.
+fn cascade_view_projection(
+    direction: Vec3,
+    center: Vec3,
+    radius: f32,
+    bounds: (Vec3, f32),
+    resolution: f32,
+) -> Mat4 {
+    let light_view = Mat4::look_at(Vec3::default(), direction, any_up(direction));
+
+    let center = light_view.transform_point(center);
+    let texel = 2.0 * radius / resolution;
+    let snapped = Vec3::new(
+        (center.x / texel).floor() * texel,
+        (center.y / texel).floor() * texel,
+        0.0,
+    );
+
+    // the light looks along -z, so everything closer to the light has a larger z
+    let (bounds_center, bounds_radius) = bounds;
+    let closest =
+        (center.z + radius).max(light_view.transform_point(bounds_center).z + bounds_radius);
+    let farthest = center.z - radius;
+
+    Mat4::orthographic(radius, radius, -closest, -farthest)
+        * Mat4::from_translation(-snapped)
+        * light_view
+}
+
+/// An up vector for looking along `direction`, which is never parallel to it
+fn any_up(direction: Vec3) -> Vec3 {
+    if direction.y.abs() > 0.99 {
+        Vec3::new(0.0, 0.0, 1.0)
+    } else {
+        Vec3::new(0.0, 1.0, 0.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn splits_increase_up_to_far() {
+        let settings = ShadowConfig::default();
+
+        for near in [0.1, 0.0] {
+            let splits = split_distances(near, 50.0, &settings);
+
+            assert_eq!(splits.len(), settings.cascade_count() as usize);
+            assert!(splits.iter().all(|split| split.is_finite()), "{splits:?}");
+            assert!(
+                splits.windows(2).all(|pair| pair[0] < pair[1]),
+                "{splits:?}"
+            );
+            assert!(splits[0] > near, "{splits:?}");
+            assert!((splits[splits.len() - 1] - 50.0).abs() < 1e-3, "{splits:?}");
+        }
+    }
+}
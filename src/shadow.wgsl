@@ -0,0 +1,77 @@
+// Depth only pass rendering the casters into one cascade of a shadow map
+
+struct TransformUniform {
+    model: mat4x4<f32>,
+    // inverse transpose of the model matrix
+    normal: mat4x4<f32>,
+}
+
+struct ShadowView {
+    view_projection: mat4x4<f32>,
+}
+
+// Same as in `pbr.wgsl`, only the base color is read to discard the holes of alpha tested materials
+struct MaterialUniform {
+    base_color_factor: vec4<f32>,
+    emissive_factor: vec3<f32>,
+    metallic_factor: f32,
+    roughness_factor: f32,
+    normal_scale: f32,
+    occlusion_strength: f32,
+    alpha_cutoff: f32,
+    base_color_tex_coord: u32,
+    metallic_roughness_tex_coord: u32,
+    normal_tex_coord: u32,
+    occlusion_tex_coord: u32,
+    emissive_tex_coord: u32,
+    alpha_mode: u32,
+    has_normal_texture: u32,
+}
+
+@group(0) @binding(0)
+var<uniform> transform: TransformUniform;
+
+@group(1) @binding(0)
+var<uniform> shadow_view: ShadowView;
+
+@group(2) @binding(0)
+var<uniform> material: MaterialUniform;
+@group(2) @binding(1)
+var base_color_texture: texture_2d<f32>;
+@group(2) @binding(2)
+var base_color_sampler: sampler;
+
+struct VertexInput {
+    @location(0) position: vec3<f32>,
+    @location(2) tex_coords_0: vec2<f32>,
+    @location(4) tex_coords_1: vec2<f32>,
+    @location(5) color: vec4<f32>,
+}
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) tex_coords_0: vec2<f32>,
+    @location(1) tex_coords_1: vec2<f32>,
+    @location(2) alpha: f32,
+}
+
+@vertex
+fn vs_main(in: VertexInput) -> VertexOutput {
+    var out: VertexOutput;
+    out.clip_position = shadow_view.view_projection * transform.model * vec4<f32>(in.position, 1.0);
+    out.tex_coords_0 = in.tex_coords_0;
+    out.tex_coords_1 = in.tex_coords_1;
+    out.alpha = in.color.a;
+    return out;
+}
+
+@fragment
+fn fs_mask(in: VertexOutput) {
+    let uv = select(in.tex_coords_0, in.tex_coords_1, material.base_color_tex_coord == 1u);
+    let alpha = material.base_color_factor.a * in.alpha
+        * textureSample(base_color_texture, base_color_sampler, uv).a;
+
+    if alpha < material.alpha_cutoff {
+        discard;
+    }
+}
@@ -0,0 +1,59 @@
+// Draws the environment cube behind the scene, at the start of the color pass
+
+struct CameraUniform {
+    view: mat4x4<f32>,
+    projection: mat4x4<f32>,
+    view_projection: mat4x4<f32>,
+    position: vec4<f32>,
+}
+
+// Same as in `pbr.wgsl`
+struct EnvironmentUniform {
+    intensity: f32,
+    // of the specular cube
+    max_mip: f32,
+    enabled: u32,
+}
+
+@group(0) @binding(0)
+var<uniform> environment: EnvironmentUniform;
+@group(0) @binding(1)
+var environment_map: texture_cube<f32>;
+@group(0) @binding(2)
+var environment_sampler: sampler;
+
+@group(1) @binding(0)
+var<uniform> camera: CameraUniform;
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) ndc: vec2<f32>,
+}
+
+// a single triangle covering the whole target
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> VertexOutput {
+    var out: VertexOutput;
+
+    let tex_coords = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+    let ndc = tex_coords * vec2<f32>(2.0, -2.0) + vec2<f32>(-1.0, 1.0);
+
+    out.clip_position = vec4<f32>(ndc, 0.0, 1.0);
+    out.ndc = ndc;
+    return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    // the view ray through the pixel, rotated into world space by the inverse of the view rotation
+    let view_direction = vec3<f32>(
+        in.ndc.x / camera.projection[0][0],
+        in.ndc.y / camera.projection[1][1],
+        -1.0,
+    );
+    let rotation = mat3x3<f32>(camera.view[0].xyz, camera.view[1].xyz, camera.view[2].xyz);
+    let direction = normalize(transpose(rotation) * view_direction);
+
+    let color = textureSampleLevel(environment_map, environment_sampler, direction, 0.0).rgb;
+    return vec4<f32>(color * environment.intensity, 1.0);
+}
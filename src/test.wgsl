@@ -1,35 +0,0 @@
-// Vertex shader
-
-@group(0) @binding(0)
-var<uniform> transform: mat4x4<f32>;
-
-@group(1) @binding(0)
-var<uniform> projection: mat4x4<f32>;
-
-struct VertexInput {
-    @location(0) position: vec3<f32>,
-    @location(1) normal: vec3<f32>,
-    @location(2) tex_coords: vec2<f32>,
-}
-
-struct VertexOutput {
-    @builtin(position) clip_position: vec4<f32>,
-    @location(0) tex_coords: vec2<f32>,
-}
-
-@vertex
-fn vs_main(
-    in: VertexInput,
-) -> VertexOutput {
-    var out: VertexOutput;
-    out.tex_coords = in.tex_coords;
-    out.clip_position = projection * transform * vec4<f32>(in.position, 1.0);
-    return out;
-}
-
-// Fragment shader
-
-@fragment
-fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
-    return vec4<f32>(in.tex_coords, 0.0, 1.0);
-}
\ No newline at end of file
@@ -0,0 +1,435 @@
+use std::collections::HashMap;
+
+use ::image::{imageops::FilterType, RgbaImage};
+use getset::Getters;
+use gltf::{
+    image::{self, Format},
+    texture::{MagFilter, MinFilter, WrappingMode},
+    Document,
+};
+
+use crate::{
+    bindgroup::{create_bindgroup, BindGroupEntryInfo},
+    graph::GraphError,
+    material::TextureRef,
+    pipeline::{self, PipelineDescriptor},
+    renderer::Configuration,
+};
+
+/// How the texels of an image are interpreted.
+/// <br>
+/// glTF stores colors (base color, emissive) in sRGB and data (normals, metallic-roughness,
+/// occlusion) linearly, sampling through an sRGB format converts to linear in hardware.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ColorSpace {
+    Srgb,
+    Linear,
+}
+
+impl ColorSpace {
+    pub fn format(&self) -> wgpu::TextureFormat {
+        match self {
+            ColorSpace::Srgb => wgpu::TextureFormat::Rgba8UnormSrgb,
+            ColorSpace::Linear => wgpu::TextureFormat::Rgba8Unorm,
+        }
+    }
+}
+
+/// An image uploaded to the GPU, with a full mip chain
+#[derive(Getters)]
+pub struct Texture {
+    #[getset(get = "pub")]
+    texture: wgpu::Texture,
+    #[getset(get = "pub")]
+    view: wgpu::TextureView,
+}
+
+impl Texture {
+    /// Uploads `rgba` to the first mip, the other mips are filled by [`MipGenerator::generate`]
+    fn new(
+        config: &Configuration,
+        rgba: &[u8],
+        width: u32,
+        height: u32,
+        color_space: ColorSpace,
+        label: &str,
+    ) -> Self {
+        let size = wgpu::Extent3d {
+            width,
+            height,
+            depth_or_array_layers: 1,
+        };
+
+        let texture = config.device.create_texture(&wgpu::TextureDescriptor {
+            label: Some(label),
+            size,
+            mip_level_count: size.max_mips(wgpu::TextureDimension::D2),
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: color_space.format(),
+            // mips are rendered from the previous level
+            usage: wgpu::TextureUsages::TEXTURE_BINDING
+                | wgpu::TextureUsages::COPY_DST
+                | wgpu::TextureUsages::RENDER_ATTACHMENT,
+            view_formats: &[],
+        });
+
+        config.queue.write_texture(
+            wgpu::ImageCopyTexture {
+                texture: &texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+                aspect: wgpu::TextureAspect::All,
+            },
+            rgba,
+            wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: Some(4 * width),
+                rows_per_image: Some(height),
+            },
+            size,
+        );
+
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+        Self { texture, view }
+    }
+
+    /// A 1x1 texture, bound in place of textures a material doesn't have
+    fn single(config: &Configuration, rgba: [u8; 4], label: &str) -> Self {
+        Self::new(config, &rgba, 1, 1, ColorSpace::Linear, label)
+    }
+}
+
+/// The textures and samplers of a document.
+/// <br>
+/// Every image is uploaded once per color space it is used in, no matter how many textures and
+/// materials reference it.
+#[derive(Getters)]
+pub struct Textures {
+    /// The source image and sampler of every glTF texture
+    sources: Vec<(usize, Option<usize>)>,
+    images: HashMap<(usize, ColorSpace), Texture>,
+    /// The samplers of the document, followed by the default sampler
+    samplers: Vec<wgpu::Sampler>,
+    /// Bound for missing color, metallic-roughness and occlusion textures, multiplying by one
+    #[getset(get = "pub")]
+    white: Texture,
+    /// Bound for missing normal textures, a normal pointing straight out of the surface
+    #[getset(get = "pub")]
+    flat_normal: Texture,
+}
+
+impl Textures {
+    /// Uploads all images referenced by the materials of `document`, `images` being the decoded
+    /// images returned by the glTF import.
+    /// <br>
+    /// Images larger than the device supports are scaled down to fit.
+    pub fn new(
+        config: &Configuration,
+        document: &Document,
+        images: &[image::Data],
+    ) -> Result<Self, GraphError> {
+        let sources = document
+            .textures()
+            .map(|texture| (texture.source().index(), texture.sampler().index()))
+            .collect::<Vec<_>>();
+
+        let mut usages = Vec::new();
+        for material in document.materials() {
+            let pbr = material.pbr_metallic_roughness();
+
+            let srgb = [pbr.base_color_texture(), material.emissive_texture()];
+            usages.extend(
+                srgb.into_iter()
+                    .flatten()
+                    .map(|info| (info.texture().index(), ColorSpace::Srgb)),
+            );
+
+            let linear = [
+                pbr.metallic_roughness_texture().map(|info| info.texture()),
+                material.normal_texture().map(|info| info.texture()),
+                material.occlusion_texture().map(|info| info.texture()),
+            ];
+            usages.extend(
+                linear
+                    .into_iter()
+                    .flatten()
+                    .map(|texture| (texture.index(), ColorSpace::Linear)),
+            );
+        }
+
+        let generator = MipGenerator::new(config);
+        let mut encoder = config
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("Mipmap encoder"),
+            });
+
+        let mut uploaded = HashMap::new();
+        for (texture, color_space) in usages {
+            let image_index = sources[texture].0;
+            if uploaded.contains_key(&(image_index, color_space)) {
+                continue;
+            }
+
+            let image = &images[image_index];
+            let name = document
+                .images()
+                .nth(image_index)
+                .and_then(|image| image.name().map(str::to_owned))
+                .unwrap_or_else(|| format!("Image {image_index}"));
+
+            let rgba = RgbaImage::from_raw(image.width, image.height, to_rgba8(image))
+                .filter(|rgba| rgba.width() > 0 && rgba.height() > 0)
+                .ok_or_else(|| {
+                    GraphError::InvalidImage(format!(
+                        "{name} is empty or has fewer pixels than its size of {}x{}",
+                        image.width, image.height
+                    ))
+                })?;
+            let rgba = fit_to_limits(config, rgba);
+
+            let texture = Texture::new(
+                config,
+                &rgba,
+                rgba.width(),
+                rgba.height(),
+                color_space,
+                &name,
+            );
+            generator.generate(config, &mut encoder, &texture.texture);
+
+            uploaded.insert((image_index, color_space), texture);
+        }
+
+        config.queue.submit(Some(encoder.finish()));
+
+        let samplers = document
+            .samplers()
+            .map(|sampler| create_sampler(config, Some(&sampler)))
+            .chain(Some(create_sampler(config, None)))
+            .collect();
+
+        Ok(Self {
+            sources,
+            images: uploaded,
+            samplers,
+            white: Texture::single(config, [255; 4], "White texture"),
+            flat_normal: Texture::single(config, [128, 128, 255, 255], "Flat normal texture"),
+        })
+    }
+
+    /// The view and sampler to bind for `texture`, or `fallback` if the material has none
+    pub fn binding<'a>(
+        &'a self,
+        texture: Option<TextureRef>,
+        color_space: ColorSpace,
+        fallback: &'a Texture,
+    ) -> (&'a wgpu::TextureView, &'a wgpu::Sampler) {
+        let default_sampler = self.samplers.last().expect("there is a default sampler");
+
+        let Some(texture) = texture else {
+            return (fallback.view(), default_sampler);
+        };
+
+        let (image, sampler) = self.sources[texture.texture];
+        let view = self.images[&(image, color_space)].view();
+        let sampler = sampler.map_or(default_sampler, |sampler| &self.samplers[sampler]);
+
+        (view, sampler)
+    }
+}
+
+/// Scales `image` down to the largest texture size of the device, keeping its aspect ratio
+fn fit_to_limits(config: &Configuration, image: RgbaImage) -> RgbaImage {
+    let max_dimension = config.device.limits().max_texture_dimension_2d;
+    if image.width() <= max_dimension && image.height() <= max_dimension {
+        return image;
+    }
+
+    let scale = max_dimension as f32 / image.width().max(image.height()) as f32;
+    let width = ((image.width() as f32 * scale) as u32).clamp(1, max_dimension);
+    let height = ((image.height() as f32 * scale) as u32).clamp(1, max_dimension);
+    ::image::imageops::resize(&image, width, height, FilterType::Triangle)
+}
+
+/// Expands any decoded image to 8 bit RGBA, the only format textures are uploaded with.
+/// <br>
+/// One and two channel images are grayscale, optionally with alpha. 16 bit and float channels are
+/// reduced to 8 bit.
+fn to_rgba8(image: &image::Data) -> Vec<u8> {
+    let (channels, bytes) = match image.format {
+        Format::R8 => (1, 1),
+        Format::R8G8 => (2, 1),
+        Format::R8G8B8 => (3, 1),
+        Format::R8G8B8A8 => (4, 1),
+        Format::R16 => (1, 2),
+        Format::R16G16 => (2, 2),
+        Format::R16G16B16 => (3, 2),
+        Format::R16G16B16A16 => (4, 2),
+        Format::R32G32B32FLOAT => (3, 4),
+        Format::R32G32B32A32FLOAT => (4, 4),
+    };
+
+    let channel = |c: &[u8]| match c.len() {
+        1 => c[0],
+        // 16 bit channels are stored in native endianness
+        2 => (u16::from_ne_bytes([c[0], c[1]]) >> 8) as u8,
+        _ => (f32::from_ne_bytes([c[0], c[1], c[2], c[3]]).clamp(0.0, 1.0) * 255.0).round() as u8,
+    };
+
+    image
+        .pixels
+        .chunks_exact(channels * bytes)
+        .flat_map(|pixel| {
+            let values: Vec<_> = pixel.chunks_exact(bytes).map(channel).collect();
+            match values[..] {
+                [l] => [l, l, l, 255],
+                [l, a] => [l, l, l, a],
+                [r, g, b] => [r, g, b, 255],
+                [r, g, b, a] => [r, g, b, a],
+                _ => unreachable!(),
+            }
+        })
+        .collect()
+}
+
+/// Maps a glTF sampler to wgpu, `None` being the default sampler of textures without one
+fn create_sampler(
+    config: &Configuration,
+    sampler: Option<&gltf::texture::Sampler>,
+) -> wgpu::Sampler {
+    let address_mode = |mode| match mode {
+        WrappingMode::ClampToEdge => wgpu::AddressMode::ClampToEdge,
+        WrappingMode::MirroredRepeat => wgpu::AddressMode::MirrorRepeat,
+        WrappingMode::Repeat => wgpu::AddressMode::Repeat,
+    };
+
+    let wrap_s = sampler.map_or(WrappingMode::Repeat, |sampler| sampler.wrap_s());
+    let wrap_t = sampler.map_or(WrappingMode::Repeat, |sampler| sampler.wrap_t());
+
+    let mag_filter = match sampler.and_then(|sampler| sampler.mag_filter()) {
+        Some(MagFilter::Nearest) => wgpu::FilterMode::Nearest,
+        Some(MagFilter::Linear) | None => wgpu::FilterMode::Linear,
+    };
+
+    // (min filter, mipmap filter, whether mips are used at all)
+    let (min_filter, mipmap_filter, mipmapped) = match sampler
+        .and_then(|sampler| sampler.min_filter())
+    {
+        Some(MinFilter::Nearest) => (wgpu::FilterMode::Nearest, wgpu::FilterMode::Nearest, false),
+        Some(MinFilter::Linear) => (wgpu::FilterMode::Linear, wgpu::FilterMode::Nearest, false),
+        Some(MinFilter::NearestMipmapNearest) => {
+            (wgpu::FilterMode::Nearest, wgpu::FilterMode::Nearest, true)
+        }
+        Some(MinFilter::LinearMipmapNearest) => {
+            (wgpu::FilterMode::Linear, wgpu::FilterMode::Nearest, true)
+        }
+        Some(MinFilter::NearestMipmapLinear) => {
+            (wgpu::FilterMode::Nearest, wgpu::FilterMode::Linear, true)
+        }
+        Some(MinFilter::LinearMipmapLinear) | None => {
+            (wgpu::FilterMode::Linear, wgpu::FilterMode::Linear, true)
+        }
+    };
+
+    config.device.create_sampler(&wgpu::SamplerDescriptor {
+        label: sampler.and_then(|sampler| sampler.name()),
+        address_mode_u: address_mode(wrap_s),
+        address_mode_v: address_mode(wrap_t),
+        address_mode_w: wgpu::AddressMode::Repeat,
+        mag_filter,
+        min_filter,
+        mipmap_filter,
+        // filters without mipmapping only ever read the first mip
+        lod_max_clamp: if mipmapped { 32.0 } else { 0.0 },
+        ..Default::default()
+    })
+}
+
+/// Fills the mip chain of textures by repeatedly rendering a level into the next smaller one
+pub(crate) struct MipGenerator {
+    sampler: wgpu::Sampler,
+}
+
+impl MipGenerator {
+    pub(crate) fn new(config: &Configuration) -> Self {
+        let sampler = config.device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("Mipmap sampler"),
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+
+        Self { sampler }
+    }
+
+    /// Records the passes rendering every mip of `texture` but the first, for each array layer
+    /// like the faces of a cube map
+    pub(crate) fn generate(
+        &self,
+        config: &Configuration,
+        encoder: &mut wgpu::CommandEncoder,
+        texture: &wgpu::Texture,
+    ) {
+        let format = texture.format();
+        let mip_view = |layer, level| {
+            texture.create_view(&wgpu::TextureViewDescriptor {
+                dimension: Some(wgpu::TextureViewDimension::D2),
+                base_mip_level: level,
+                mip_level_count: Some(1),
+                base_array_layer: layer,
+                array_layer_count: Some(1),
+                ..Default::default()
+            })
+        };
+
+        let levels = (0..texture.depth_or_array_layers())
+            .flat_map(|layer| (1..texture.mip_level_count()).map(move |level| (layer, level)));
+        for (layer, level) in levels {
+            let source = mip_view(layer, level - 1);
+            let target = mip_view(layer, level);
+
+            let bind_group = create_bindgroup(
+                config,
+                &[
+                    BindGroupEntryInfo::new(wgpu::ShaderStages::FRAGMENT, &source),
+                    BindGroupEntryInfo::new(wgpu::ShaderStages::FRAGMENT, &self.sampler),
+                ],
+                "Mipmap source",
+            );
+
+            let pipeline = pipeline::create(
+                config,
+                &[],
+                &[&bind_group.layout],
+                include_str!("mipmap.wgsl"),
+                &PipelineDescriptor::new(config, wgpu::PrimitiveTopology::TriangleList)
+                    .cull_mode(None)
+                    .depth_stencil(None)
+                    .targets(vec![Some(format.into())]),
+                Some(&format!("Mipmap {format:?}")),
+            );
+
+            let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Mipmap pass"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: &target,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+                        store: wgpu::StoreOp::Store,
+                    },
+                })],
+                depth_stencil_attachment: None,
+                timestamp_writes: None,
+                occlusion_query_set: None,
+            });
+
+            pass.set_pipeline(&pipeline);
+            pass.set_bind_group(0, &bind_group.group, &[]);
+            pass.draw(0..3, 0..1);
+        }
+    }
+}
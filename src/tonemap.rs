@@ -0,0 +1,256 @@
+use std::{str::FromStr, sync::Arc};
+
+use getset::Getters;
+
+use crate::{
+    bindgroup::{create_bindgroup, BindGroupEntryInfo, BindGroupInfo},
+    exposure::AutoExposure,
+    math::vec::Vec4,
+    pipeline::{self, PipelineDescriptor},
+    renderer::Configuration,
+    uniform_buffer::create_uniform_buffer,
+};
+
+/// The curve mapping the exposed HDR colors into the displayable range
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Tonemapper {
+    /// Fit of the ACES filmic look, contrasty with saturated highlights
+    Aces,
+    /// Desaturates highlights towards white the way film does
+    Agx,
+    /// Compresses the luminance only, simple but dull
+    Reinhard,
+    /// Khronos PBR Neutral, keeps the base colors of materials as authored
+    #[default]
+    PbrNeutral,
+}
+
+impl Tonemapper {
+    /// The `tonemapper` of `tonemap.wgsl`
+    fn index(self) -> u32 {
+        match self {
+            Tonemapper::Aces => 0,
+            Tonemapper::Agx => 1,
+            Tonemapper::Reinhard => 2,
+            Tonemapper::PbrNeutral => 3,
+        }
+    }
+}
+
+impl FromStr for Tonemapper {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "aces" => Ok(Tonemapper::Aces),
+            "agx" => Ok(Tonemapper::Agx),
+            "reinhard" => Ok(Tonemapper::Reinhard),
+            "neutral" => Ok(Tonemapper::PbrNeutral),
+            _ => Err(format!(
+                "unknown tonemapper '{name}', expected aces, agx, reinhard or neutral"
+            )),
+        }
+    }
+}
+
+/// How the HDR target is exposed and tonemapped onto the surface
+#[derive(Clone, Copy, Debug)]
+pub struct TonemapConfig {
+    pub tonemapper: Tonemapper,
+    /// In EV, every step doubles the brightness.
+    /// <br>
+    /// With [`TonemapConfig::auto_exposure`], this compensates the metered exposure.
+    pub exposure: f32,
+    /// Exposes the average luminance of the frame to middle grey, measured with a histogram on
+    /// the GPU
+    pub auto_exposure: bool,
+    /// Darkest luminance told apart by the histogram, in EV
+    pub min_luminance: f32,
+    /// Brightest luminance told apart by the histogram, in EV
+    pub max_luminance: f32,
+    /// How fast the automatic exposure follows changes, the metered difference shrinks by about
+    /// two thirds in `1 / adaptation_speed` seconds
+    pub adaptation_speed: f32,
+}
+
+impl Default for TonemapConfig {
+    fn default() -> Self {
+        Self {
+            tonemapper: Tonemapper::default(),
+            exposure: 0.0,
+            auto_exposure: false,
+            min_luminance: -10.0,
+            max_luminance: 10.0,
+            adaptation_speed: 2.0,
+        }
+    }
+}
+
+/// The color target of the main pass, which keeps radiance beyond 1 for the tonemapping.
+/// <br>
+/// Has to be recreated whenever the surface size changes.
+#[derive(Getters)]
+pub struct HdrTarget {
+    #[getset(get = "pub")]
+    texture: wgpu::Texture,
+    #[getset(get = "pub")]
+    view: wgpu::TextureView,
+}
+
+impl HdrTarget {
+    pub const FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+
+    pub fn new(config: &Configuration) -> Self {
+        let texture = config.device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("HDR target"),
+            size: wgpu::Extent3d {
+                width: config.size.width.max(1),
+                height: config.size.height.max(1),
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: Self::FORMAT,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+            view_formats: &[],
+        });
+
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+        Self { texture, view }
+    }
+}
+
+/// The pass resolving the [`HdrTarget`] onto the surface, metering it first with
+/// [`AutoExposure`] if enabled
+#[derive(Getters)]
+pub struct Tonemap {
+    /// The settings of [`TonemapConfig`] read by `tonemap.wgsl`
+    #[getset(get = "pub")]
+    uniform: wgpu::Buffer,
+    /// The adapted average luminance, written by [`AutoExposure`]
+    #[getset(get = "pub")]
+    exposure: wgpu::Buffer,
+    #[getset(get = "pub")]
+    auto_exposure: Option<AutoExposure>,
+    pipeline: Arc<wgpu::RenderPipeline>,
+    bind_group: BindGroupInfo,
+}
+
+impl Tonemap {
+    pub fn new(config: &Configuration, target: &HdrTarget) -> Self {
+        let settings = &config.settings.tonemap;
+
+        let uniform = create_uniform_buffer(
+            config,
+            "Tonemap uniform",
+            &Vec4::new(
+                f32::from_bits(settings.tonemapper.index()),
+                settings.exposure,
+                f32::from_bits(settings.auto_exposure as u32),
+                f32::from_bits(!config.surface_config.format.is_srgb() as u32),
+            )
+            .bytes(),
+        );
+
+        let exposure = config.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Exposure"),
+            size: 16,
+            usage: wgpu::BufferUsages::STORAGE,
+            mapped_at_creation: false,
+        });
+
+        let auto_exposure = settings
+            .auto_exposure
+            .then(|| AutoExposure::new(config, target, &exposure));
+
+        let bind_group = Self::bind_group(config, target, &uniform, &exposure);
+
+        let pipeline = pipeline::create(
+            config,
+            &[],
+            &[&bind_group.layout],
+            include_str!("tonemap.wgsl"),
+            &PipelineDescriptor::new(config, wgpu::PrimitiveTopology::TriangleList)
+                .cull_mode(None)
+                .depth_stencil(None)
+                .targets(vec![Some(config.surface_config.format.into())]),
+            Some("Tonemap"),
+        );
+
+        Self {
+            uniform,
+            exposure,
+            auto_exposure,
+            pipeline,
+            bind_group,
+        }
+    }
+
+    /// Binds the new `target` after a resize
+    pub fn resize(&mut self, config: &Configuration, target: &HdrTarget) {
+        self.bind_group = Self::bind_group(config, target, &self.uniform, &self.exposure);
+
+        if let Some(auto_exposure) = &mut self.auto_exposure {
+            auto_exposure.resize(config, target, &self.exposure);
+        }
+    }
+
+    /// Records the metering of the HDR target if there is auto exposure, and the pass
+    /// tonemapping it into `view`, which has the format of [`Configuration::surface_config`]
+    pub fn render(
+        &mut self,
+        config: &Configuration,
+        encoder: &mut wgpu::CommandEncoder,
+        view: &wgpu::TextureView,
+    ) {
+        if let Some(auto_exposure) = &mut self.auto_exposure {
+            auto_exposure.meter(config, encoder);
+        }
+
+        let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Tonemap Pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                    store: wgpu::StoreOp::Store,
+                },
+            })],
+            ..Default::default()
+        });
+
+        pass.set_pipeline(&self.pipeline);
+        pass.set_bind_group(0, &self.bind_group.group, &[]);
+        pass.draw(0..3, 0..1);
+    }
+
+    fn bind_group(
+        config: &Configuration,
+        target: &HdrTarget,
+        uniform: &wgpu::Buffer,
+        exposure: &wgpu::Buffer,
+    ) -> BindGroupInfo {
+        create_bindgroup(
+            config,
+            &[
+                BindGroupEntryInfo::new(
+                    wgpu::ShaderStages::FRAGMENT,
+                    uniform.as_entire_buffer_binding(),
+                ),
+                BindGroupEntryInfo::new(wgpu::ShaderStages::FRAGMENT, target.view()).texture(
+                    wgpu::TextureViewDimension::D2,
+                    wgpu::TextureSampleType::Float { filterable: false },
+                ),
+                BindGroupEntryInfo::new(
+                    wgpu::ShaderStages::FRAGMENT,
+                    exposure.as_entire_buffer_binding(),
+                )
+                .storage(true),
+            ],
+            "Tonemap",
+        )
+    }
+}
@@ -0,0 +1,155 @@
+// Exposes the HDR target and maps it to the displayable range of the surface
+
+const TONEMAP_ACES: u32 = 0u;
+const TONEMAP_AGX: u32 = 1u;
+const TONEMAP_REINHARD: u32 = 2u;
+
+// Luminance an exposure of 0 EV maps the metered average to, middle grey
+const MIDDLE_GREY: f32 = 0.18;
+
+// See `TonemapUniform` in `tonemap.rs`
+struct TonemapUniform {
+    tonemapper: u32,
+    // in EV, compensation on top of the metered exposure with auto exposure
+    exposure: f32,
+    auto_exposure: u32,
+    // the surface has no sRGB format, which would encode in hardware
+    encode_srgb: u32,
+}
+
+// Same as in `exposure.wgsl`
+struct Exposure {
+    luminance_ev: f32,
+}
+
+@group(0) @binding(0)
+var<uniform> tonemap: TonemapUniform;
+@group(0) @binding(1)
+var hdr: texture_2d<f32>;
+@group(0) @binding(2)
+var<storage, read> exposure: Exposure;
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+}
+
+// a single triangle covering the whole target
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> VertexOutput {
+    var out: VertexOutput;
+
+    let tex_coords = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+
+    out.clip_position = vec4<f32>(tex_coords * vec2<f32>(2.0, -2.0) + vec2<f32>(-1.0, 1.0), 0.0, 1.0);
+    return out;
+}
+
+fn luminance(color: vec3<f32>) -> f32 {
+    return dot(color, vec3<f32>(0.2126, 0.7152, 0.0722));
+}
+
+// Stephen Hill's fit of the ACES reference rendering and output transforms
+// https://github.com/TheRealMJP/BakingLab/blob/master/BakingLab/ACES.hlsl
+fn aces(color: vec3<f32>) -> vec3<f32> {
+    let input = mat3x3<f32>(
+        vec3<f32>(0.59719, 0.07600, 0.02840),
+        vec3<f32>(0.35458, 0.90834, 0.13383),
+        vec3<f32>(0.04823, 0.01566, 0.83777),
+    );
+    let output = mat3x3<f32>(
+        vec3<f32>(1.60475, -0.10208, -0.00327),
+        vec3<f32>(-0.53108, 1.10813, -0.07276),
+        vec3<f32>(-0.07367, -0.00605, 1.07602),
+    );
+
+    let v = input * color;
+    let a = v * (v + 0.0245786) - 0.000090537;
+    let b = v * (0.983729 * v + 0.4329510) + 0.238081;
+    return clamp(output * (a / b), vec3<f32>(0.0), vec3<f32>(1.0));
+}
+
+// Minimal AgX with the polynomial fit of its default contrast curve
+// https://iolite-engine.com/blog_posts/minimal_agx_implementation
+fn agx(color: vec3<f32>) -> vec3<f32> {
+    let inset = mat3x3<f32>(
+        vec3<f32>(0.842479062253094, 0.0423282422610123, 0.0423756549057051),
+        vec3<f32>(0.0784335999999992, 0.878468636469772, 0.0784336),
+        vec3<f32>(0.0792237451477643, 0.0791661274605434, 0.879142973793104),
+    );
+    let outset = mat3x3<f32>(
+        vec3<f32>(1.19687900512017, -0.0528968517574562, -0.0529716355144438),
+        vec3<f32>(-0.0980208811401368, 1.15190312990417, -0.0980434501171241),
+        vec3<f32>(-0.0990297440797205, -0.0989611768448433, 1.15107367264116),
+    );
+    let min_ev = -12.47393;
+    let max_ev = 4.026069;
+
+    var v = inset * max(color, vec3<f32>(1e-10));
+    v = (clamp(log2(v), vec3<f32>(min_ev), vec3<f32>(max_ev)) - min_ev) / (max_ev - min_ev);
+
+    let x2 = v * v;
+    let x4 = x2 * x2;
+    v = 15.5 * x4 * x2 - 40.14 * x4 * v + 31.96 * x4 - 6.868 * x2 * v + 0.4298 * x2 + 0.1191 * v - 0.00232;
+
+    // the curve ends in display encoding, which the surface applies again
+    return pow(max(outset * v, vec3<f32>(0.0)), vec3<f32>(2.2));
+}
+
+// Reinhard on the luminance, which keeps the hue of bright colors
+fn reinhard(color: vec3<f32>) -> vec3<f32> {
+    return color / (1.0 + luminance(color));
+}
+
+// Khronos PBR Neutral, keeps base colors of materials up to 0.8 as they are
+// https://github.com/KhronosGroup/ToneMapping/blob/main/PBR_Neutral/pbrNeutral.glsl
+fn pbr_neutral(color: vec3<f32>) -> vec3<f32> {
+    let start_compression = 0.8 - 0.04;
+    let desaturation = 0.15;
+
+    let x = min(color.r, min(color.g, color.b));
+    let offset = select(0.04, x - 6.25 * x * x, x < 0.08);
+    var c = color - offset;
+
+    let peak = max(c.r, max(c.g, c.b));
+    if peak < start_compression {
+        return c;
+    }
+
+    let d = 1.0 - start_compression;
+    let new_peak = 1.0 - d * d / (peak + d - start_compression);
+    c *= new_peak / peak;
+
+    let g = 1.0 - 1.0 / (desaturation * (peak - new_peak) + 1.0);
+    return mix(c, vec3<f32>(new_peak), g);
+}
+
+fn srgb_encode(color: vec3<f32>) -> vec3<f32> {
+    let low = color * 12.92;
+    let high = 1.055 * pow(color, vec3<f32>(1.0 / 2.4)) - 0.055;
+    return select(high, low, color <= vec3<f32>(0.0031308));
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    var color = textureLoad(hdr, vec2<i32>(in.clip_position.xy), 0).rgb;
+
+    var ev = tonemap.exposure;
+    if tonemap.auto_exposure != 0u {
+        ev += log2(MIDDLE_GREY) - exposure.luminance_ev;
+    }
+    color *= exp2(ev);
+
+    switch tonemap.tonemapper {
+        case TONEMAP_ACES: { color = aces(color); }
+        case TONEMAP_AGX: { color = agx(color); }
+        case TONEMAP_REINHARD: { color = reinhard(color); }
+        default: { color = pbr_neutral(color); }
+    }
+
+    color = clamp(color, vec3<f32>(0.0), vec3<f32>(1.0));
+    if tonemap.encode_srgb != 0u {
+        color = srgb_encode(color);
+    }
+
+    return vec4<f32>(color, 1.0);
+}
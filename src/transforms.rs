@@ -0,0 +1,105 @@
+use getset::{CopyGetters, Getters};
+
+use crate::{
+    bindgroup::{create_bindgroup, BindGroupEntryInfo, BindGroupInfo},
+    math::mat::Mat4,
+    node::Node,
+    renderer::Configuration,
+};
+
+/// Size of `TransformUniform` in `pbr.wgsl`, the model matrix followed by the normal matrix
+const TRANSFORM_SIZE: u64 = 2 * 64;
+
+/// The world transforms of all nodes in a single uniform buffer.
+/// <br>
+/// Every node has a slot, which draws select with a dynamic offset into the one bind group, so
+/// that neither buffers nor bind groups scale with the number of objects.
+#[derive(Getters, CopyGetters)]
+pub struct TransformStorage {
+    #[getset(get = "pub")]
+    buffer: wgpu::Buffer,
+    #[getset(get = "pub")]
+    bind_group: BindGroupInfo,
+    /// Distance between slots, the size of a transform rounded up to the offset alignment
+    #[getset(get_copy = "pub")]
+    stride: u64,
+}
+
+impl TransformStorage {
+    /// Creates a slot for every node, filled with its current world transform
+    pub fn new(config: &Configuration, nodes: &[Node]) -> Self {
+        let alignment = config.device.limits().min_uniform_buffer_offset_alignment as u64;
+        let stride = TRANSFORM_SIZE.next_multiple_of(alignment);
+
+        let buffer = config.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Transform storage"),
+            // there always is one slot, so the binding is valid for empty scenes too
+            size: stride * nodes.len().max(1) as u64,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let bind_group = create_bindgroup(
+            config,
+            &[BindGroupEntryInfo::dynamic(
+                wgpu::ShaderStages::VERTEX,
+                &buffer,
+                wgpu::BufferSize::new(TRANSFORM_SIZE).expect("transforms aren't empty"),
+            )],
+            "Transform storage",
+        );
+
+        let storage = Self {
+            buffer,
+            bind_group,
+            stride,
+        };
+
+        let all: Vec<_> = (0..nodes.len()).collect();
+        storage.write(config, nodes, &all);
+
+        storage
+    }
+
+    /// The dynamic offset selecting the transform of `node`
+    pub fn offset(&self, node: usize) -> u32 {
+        (node as u64 * self.stride) as u32
+    }
+
+    /// Uploads the world transforms of the `changed` nodes, sorted by index.
+    /// <br>
+    /// Runs of neighbouring nodes, like the subtree of a moved node, are written with a single
+    /// `write_buffer`.
+    pub fn write(&self, config: &Configuration, nodes: &[Node], changed: &[usize]) {
+        let mut run: Vec<u8> = Vec::new();
+        let mut run_start = 0;
+
+        for (i, &index) in changed.iter().enumerate() {
+            if run.is_empty() {
+                run_start = index;
+            }
+
+            run.extend(transform_bytes(&nodes[index].world()));
+
+            let continues = changed.get(i + 1) == Some(&(index + 1));
+            if continues {
+                run.resize(run.len() + (self.stride - TRANSFORM_SIZE) as usize, 0);
+            } else {
+                config
+                    .queue
+                    .write_buffer(&self.buffer, self.offset(run_start) as u64, &run);
+                run.clear();
+            }
+        }
+    }
+}
+
+/// The model matrix followed by the normal matrix, matching `TransformUniform` in `pbr.wgsl`
+fn transform_bytes(world: &Mat4) -> Vec<u8> {
+    // the inverse transpose keeps normals perpendicular under non-uniform scale
+    let normal = world.inverse().unwrap_or_default().transpose();
+
+    let mut bytes = world.bytes();
+    bytes.extend(normal.bytes());
+    bytes
+}
@@ -0,0 +1,222 @@
+use gltf::{accessor::DataType, Semantic};
+
+use crate::{model_buffer_info::ModelBufferIndo, view::ViewInfo};
+
+/// A vertex attribute a shader reads, at a location chosen by the shader.
+/// <br>
+/// Custom attributes are declared with [`Semantic::Extras`], leaving out the leading `_`.
+#[derive(Clone, Debug)]
+pub struct VertexInput {
+    pub semantic: Semantic,
+    pub location: u32,
+}
+
+impl VertexInput {
+    pub fn new(semantic: Semantic, location: u32) -> Self {
+        Self { semantic, location }
+    }
+
+    /// The attributes read by the renderer's own shaders, at their stable locations
+    pub fn standard() -> Vec<Self> {
+        vec![
+            Self::new(Semantic::Positions, 0),
+            Self::new(Semantic::Normals, 1),
+            Self::new(Semantic::TexCoords(0), 2),
+            Self::new(Semantic::Tangents, 3),
+            Self::new(Semantic::TexCoords(1), 4),
+            Self::new(Semantic::Colors(0), 5),
+            Self::new(Semantic::Joints(0), 6),
+            Self::new(Semantic::Weights(0), 7),
+        ]
+    }
+
+    /// The attributes read by `pbr.wgsl` and `shadow.wgsl`, which don't skin
+    pub fn pbr() -> Vec<Self> {
+        Self::standard()
+            .into_iter()
+            .filter(|input| !matches!(input.semantic, Semantic::Joints(_) | Semantic::Weights(_)))
+            .collect()
+    }
+}
+
+/// One vertex buffer slot of a primitive, either reading an accessor or a default value
+#[derive(Clone, Copy, Debug)]
+pub struct VertexSlot {
+    pub view: ViewInfo,
+    /// `Instance` for default values, so that every vertex of the single instance reads the same
+    pub step_mode: wgpu::VertexStepMode,
+    pub attribute: [wgpu::VertexAttribute; 1],
+}
+
+/// The vertex buffers of a primitive, ordered as the slots they are bound to
+pub struct VertexLayout {
+    slots: Vec<VertexSlot>,
+}
+
+impl VertexLayout {
+    /// Maps the attributes of `primitive` to the `inputs` of a shader, substituting defaults for
+    /// missing ones. Attributes the shader doesn't read are not bound.
+    pub fn new(
+        inputs: &[VertexInput],
+        primitive: &gltf::Primitive,
+        buffer_info: &ModelBufferIndo,
+    ) -> Self {
+        let slots = inputs
+            .iter()
+            .map(|input| {
+                let attribute = primitive.get(&input.semantic).and_then(|accessor| {
+                    let (format, _) = format(&accessor)?;
+                    Some((buffer_info.vertex_view(&accessor)?, format))
+                });
+
+                let (view, format, step_mode) = match attribute {
+                    Some((view, format)) => (view, format, wgpu::VertexStepMode::Vertex),
+                    None => {
+                        let (view, format) = buffer_info.default_view(&input.semantic);
+                        (view, format, wgpu::VertexStepMode::Instance)
+                    }
+                };
+
+                VertexSlot {
+                    view,
+                    step_mode,
+                    attribute: [wgpu::VertexAttribute {
+                        format,
+                        offset: 0,
+                        shader_location: input.location,
+                    }],
+                }
+            })
+            .collect();
+
+        Self { slots }
+    }
+
+    pub fn slots(&self) -> &[VertexSlot] {
+        &self.slots
+    }
+
+    pub fn buffer_layouts(&self) -> Vec<wgpu::VertexBufferLayout<'_>> {
+        self.slots
+            .iter()
+            .map(|slot| wgpu::VertexBufferLayout {
+                array_stride: slot.view.stride,
+                step_mode: slot.step_mode,
+                attributes: &slot.attribute,
+            })
+            .collect()
+    }
+}
+
+/// The format an accessor is uploaded with and its number of components after padding.
+/// <br>
+/// wgpu neither has three component nor single component 8 and 16 bit formats and strides have to
+/// be a multiple of 4, so 8 bit data is padded to 4 and 16 bit data to 2 or 4 components.
+/// Returns `None` for matrices, which can't be vertex attributes.
+pub fn format(accessor: &gltf::Accessor) -> Option<(wgpu::VertexFormat, usize)> {
+    use wgpu::VertexFormat as F;
+
+    let components = match accessor.dimensions() {
+        gltf::accessor::Dimensions::Scalar => 1,
+        gltf::accessor::Dimensions::Vec2 => 2,
+        gltf::accessor::Dimensions::Vec3 => 3,
+        gltf::accessor::Dimensions::Vec4 => 4,
+        _ => return None,
+    };
+    let normalized = accessor.normalized();
+
+    Some(match (accessor.data_type(), components) {
+        (DataType::F32, 1) => (F::Float32, 1),
+        (DataType::F32, 2) => (F::Float32x2, 2),
+        (DataType::F32, 3) => (F::Float32x3, 3),
+        (DataType::F32, _) => (F::Float32x4, 4),
+        (DataType::U32, 1) => (F::Uint32, 1),
+        (DataType::U32, 2) => (F::Uint32x2, 2),
+        (DataType::U32, 3) => (F::Uint32x3, 3),
+        (DataType::U32, _) => (F::Uint32x4, 4),
+        (DataType::U16, 1 | 2) if normalized => (F::Unorm16x2, 2),
+        (DataType::U16, _) if normalized => (F::Unorm16x4, 4),
+        (DataType::U16, 1 | 2) => (F::Uint16x2, 2),
+        (DataType::U16, _) => (F::Uint16x4, 4),
+        (DataType::I16, 1 | 2) if normalized => (F::Snorm16x2, 2),
+        (DataType::I16, _) if normalized => (F::Snorm16x4, 4),
+        (DataType::I16, 1 | 2) => (F::Sint16x2, 2),
+        (DataType::I16, _) => (F::Sint16x4, 4),
+        (DataType::U8, _) if normalized => (F::Unorm8x4, 4),
+        (DataType::U8, _) => (F::Uint8x4, 4),
+        (DataType::I8, _) if normalized => (F::Snorm8x4, 4),
+        (DataType::I8, _) => (F::Sint8x4, 4),
+    })
+}
+
+/// Pads every element of tightly packed accessor data to the component count of [`format`].
+/// <br>
+/// Added components are 0, except for a fourth one, which is 1 like shaders expand missing `w`s.
+pub fn pad(accessor: &gltf::Accessor, data: Vec<u8>) -> Vec<u8> {
+    let Some((_, components)) = format(accessor) else {
+        return data;
+    };
+
+    let component_size = accessor.data_type().size();
+    let element_size = accessor.size();
+    let padding = components * component_size - element_size;
+
+    if padding == 0 {
+        return data;
+    }
+
+    let zero = vec![0; component_size];
+    let one: &[u8] = match (accessor.data_type(), accessor.normalized()) {
+        (DataType::U8, true) => &[0xff],
+        (DataType::I8, true) => &[0x7f],
+        (DataType::U16, true) => &[0xff, 0xff],
+        (DataType::I16, true) => &[0xff, 0x7f],
+        (DataType::U8 | DataType::I8, false) => &[1],
+        _ => &[1, 0],
+    };
+
+    let mut result = Vec::with_capacity(data.len() / element_size * (element_size + padding));
+    let last: &[u8] = if components == 4 { one } else { &zero };
+    for element in data.chunks_exact(element_size) {
+        result.extend_from_slice(element);
+        result.resize(result.len() + padding - component_size, 0);
+        result.extend_from_slice(last);
+    }
+
+    result
+}
+
+/// Value and format bound for an attribute a shader reads but a mesh lacks
+pub fn default_value(semantic: &Semantic) -> (wgpu::VertexFormat, [u32; 4]) {
+    let float = |v: [f32; 4]| v.map(f32::to_bits);
+
+    match semantic {
+        Semantic::Normals => (wgpu::VertexFormat::Float32x4, float([0.0, 0.0, 1.0, 0.0])),
+        // a `w` of 0 tells shaders to derive tangents from texture coordinates instead
+        Semantic::Tangents => (wgpu::VertexFormat::Float32x4, float([0.0; 4])),
+        Semantic::Colors(_) => (wgpu::VertexFormat::Float32x4, float([1.0; 4])),
+        Semantic::Joints(_) => (wgpu::VertexFormat::Uint32x4, [0; 4]),
+        _ => (wgpu::VertexFormat::Float32x4, float([0.0; 4])),
+    }
+}
+
+/// Every distinct default, the index into this list is returned by [`default_index`]
+pub fn default_semantics() -> [Semantic; 5] {
+    [
+        Semantic::Positions,
+        Semantic::Normals,
+        Semantic::Tangents,
+        Semantic::Colors(0),
+        Semantic::Joints(0),
+    ]
+}
+
+pub fn default_index(semantic: &Semantic) -> usize {
+    match semantic {
+        Semantic::Normals => 1,
+        Semantic::Tangents => 2,
+        Semantic::Colors(_) => 3,
+        Semantic::Joints(_) => 4,
+        _ => 0,
+    }
+}
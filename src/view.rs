@@ -1,21 +1,28 @@
 use getset::Getters;
 
-#[derive(Getters, Clone, Copy)]
+#[derive(Getters, Clone, Copy, Debug)]
 pub struct ViewInfo {
     #[getset(get = "pub")]
     pub lenght: u64,
     #[getset(get = "pub")]
     pub offset: u64,
+    /// Size of one element in bytes, elements are tightly packed
+    #[getset(get = "pub")]
+    pub stride: u64,
 }
 
 impl ViewInfo {
-    pub fn new(lenght: u64, offset: u64) -> Self {
-        Self { lenght, offset }
+    pub fn new(lenght: u64, offset: u64, stride: u64) -> Self {
+        Self {
+            lenght,
+            offset,
+            stride,
+        }
     }
 }
 
 #[derive(Clone, Copy)]
 pub enum ViewType {
     Vertex(ViewInfo),
-    Index(ViewInfo),
+    Index(ViewInfo, wgpu::IndexFormat),
 }
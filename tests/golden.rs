@@ -0,0 +1,341 @@
+//! Renders reference scenes headless and compares them with the golden images in `tests/golden`.
+//! <br>
+//! Run `RENDER_BLESS=1 cargo test --test golden` to accept the current output as new goldens.
+//! On failure, the frame and an image highlighting the differing pixels are written next to
+//! each other into `target/tmp/golden`.
+//! <br>
+//! The tests fail without a GPU adapter. On machines which can't render at all, `RENDER_SKIP_GPU=1`
+//! skips them instead.
+
+use std::{
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use image::{Rgba, RgbaImage};
+use render::{
+    depth::DepthConfig,
+    environment::EnvironmentConfig,
+    renderer::{RenderSettings, RendererError, RendererState},
+    shadow::ShadowConfig,
+    tonemap::{TonemapConfig, Tonemapper},
+};
+use winit::dpi::PhysicalSize;
+
+const SIZE: PhysicalSize<u32> = PhysicalSize::new(320, 240);
+
+/// Largest difference of a channel, out of 255, which still counts as the same pixel.
+/// Absorbs rounding differences between drivers.
+const CHANNEL_TOLERANCE: u8 = 3;
+
+/// Fraction of pixels which may differ, like a few pixels along edges rasterized differently
+const PIXEL_TOLERANCE: f64 = 0.001;
+
+/// Devices are created one at a time, software adapters don't cope well with parallel setup
+static RENDER_LOCK: Mutex<()> = Mutex::new(());
+
+#[test]
+fn bundled_scene() {
+    check("bundled_scene", None, RenderSettings::default());
+}
+
+#[test]
+fn bundled_scene_reverse_z() {
+    let settings = RenderSettings {
+        depth: DepthConfig {
+            reverse_z: true,
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+
+    // reverse-Z only changes depth precision, the image has to stay the same
+    check("bundled_scene", None, settings);
+}
+
+#[test]
+fn cascade_debug_view() {
+    let settings = RenderSettings {
+        shadows: ShadowConfig {
+            debug_cascades: true,
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+
+    check(
+        "cascade_debug_view",
+        Some("tests/scenes/shadows.gltf"),
+        settings,
+    );
+}
+
+#[test]
+fn cascaded_shadows() {
+    check(
+        "cascaded_shadows",
+        Some("tests/scenes/shadows.gltf"),
+        RenderSettings::default(),
+    );
+}
+
+#[test]
+fn environment_lighting() {
+    let settings = RenderSettings {
+        environment: sky(),
+        ..Default::default()
+    };
+
+    check("environment_lighting", None, settings);
+}
+
+#[test]
+fn tonemap_aces() {
+    check_tonemapper("tonemap_aces", Tonemapper::Aces);
+}
+
+#[test]
+fn tonemap_agx() {
+    check_tonemapper("tonemap_agx", Tonemapper::Agx);
+}
+
+#[test]
+fn tonemap_reinhard() {
+    check_tonemapper("tonemap_reinhard", Tonemapper::Reinhard);
+}
+
+#[test]
+fn auto_exposure() {
+    let settings = RenderSettings {
+        environment: sky(),
+        tonemap: TonemapConfig {
+            auto_exposure: true,
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+
+    // the bright sky fills most of the frame, so the scene is exposed darker
+    check("auto_exposure", None, settings);
+}
+
+#[test]
+fn point_and_spot_shadows() {
+    check(
+        "point_and_spot_shadows",
+        Some("tests/scenes/local_shadows.gltf"),
+        RenderSettings::default(),
+    );
+}
+
+#[test]
+fn shadow_tile_budget() {
+    let settings = RenderSettings {
+        shadows: ShadowConfig {
+            // enough for the spot light closest to the camera, but not for the six lamp faces
+            tile_budget: 4,
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+
+    check(
+        "shadow_tile_budget",
+        Some("tests/scenes/local_shadows.gltf"),
+        settings,
+    );
+}
+
+#[test]
+fn oversized_shadow_atlas() {
+    let settings = RenderSettings {
+        shadows: ShadowConfig {
+            // clamped to the device limit, which holds fewer tiles per row than requested
+            atlas_resolution: 1 << 20,
+            tile_resolution: 2048,
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+
+    check(
+        "oversized_shadow_atlas",
+        Some("tests/scenes/local_shadows.gltf"),
+        settings,
+    );
+}
+
+#[test]
+fn textured_quad() {
+    check(
+        "textured_quad",
+        Some("tests/scenes/textured_quad.gltf"),
+        lit(),
+    );
+}
+
+#[test]
+fn alpha_modes() {
+    check("alpha_modes", Some("tests/scenes/alpha_modes.gltf"), lit());
+}
+
+/// The scenes of the material tests have no lights of their own
+fn lit() -> RenderSettings {
+    RenderSettings {
+        fallback_light: true,
+        ..Default::default()
+    }
+}
+
+/// Neither texture coordinates nor tangents, the normal mapped quad has no direction to derive
+/// its tangent from
+#[test]
+fn untextured() {
+    check(
+        "untextured",
+        Some("tests/scenes/untextured.gltf"),
+        RenderSettings::default(),
+    );
+}
+
+#[test]
+fn punctual_lights() {
+    check(
+        "punctual_lights",
+        Some("tests/scenes/lights.gltf"),
+        RenderSettings::default(),
+    );
+}
+
+/// The bundled scene in front of the test sky, whose sun clips without tonemapping
+fn check_tonemapper(golden: &str, tonemapper: Tonemapper) {
+    let settings = RenderSettings {
+        environment: sky(),
+        tonemap: TonemapConfig {
+            tonemapper,
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+
+    check(golden, None, settings);
+}
+
+fn sky() -> EnvironmentConfig {
+    EnvironmentConfig {
+        path: Some(manifest_path("tests/scenes/sky.hdr")),
+        ..Default::default()
+    }
+}
+
+fn check(golden: &str, scene: Option<&str>, settings: RenderSettings) {
+    let Some(frame) = render(scene, settings) else {
+        return;
+    };
+
+    let golden_path = manifest_path(&format!("tests/golden/{golden}.png"));
+
+    if std::env::var_os("RENDER_BLESS").is_some() {
+        frame.save(&golden_path).expect("golden can be written");
+        return;
+    }
+
+    let expected = image::open(&golden_path)
+        .unwrap_or_else(|err| {
+            panic!(
+                "missing golden {}, bless it with RENDER_BLESS=1: {err}",
+                golden_path.display()
+            )
+        })
+        .to_rgba8();
+
+    assert_eq!(
+        expected.dimensions(),
+        frame.dimensions(),
+        "golden {golden} has another size than the frame"
+    );
+
+    let (diff, differing) = diff(&expected, &frame);
+    let fraction = differing as f64 / (frame.width() * frame.height()) as f64;
+
+    if fraction > PIXEL_TOLERANCE {
+        let output = Path::new(env!("CARGO_TARGET_TMPDIR")).join("golden");
+        std::fs::create_dir_all(&output).expect("output directory can be created");
+
+        // named after the test, as several tests can share a golden
+        let test = std::thread::current().name().unwrap_or(golden).to_owned();
+        let report = output.join(format!("{test}.png"));
+        side_by_side(&frame, &diff)
+            .save(&report)
+            .expect("diff can be written");
+
+        panic!(
+            "{golden}: {differing} pixels ({:.2}%) differ from the golden, see {}",
+            fraction * 100.0,
+            report.display()
+        );
+    }
+}
+
+/// Renders a frame, or returns `None` if there is no adapter and the test is skipped with
+/// `RENDER_SKIP_GPU`
+fn render(scene: Option<&str>, settings: RenderSettings) -> Option<RgbaImage> {
+    let _lock = RENDER_LOCK.lock().unwrap_or_else(|err| err.into_inner());
+
+    let scene = scene.map(manifest_path);
+    let renderer = pollster::block_on(RendererState::headless(SIZE, scene.as_deref(), settings));
+
+    let mut renderer = match renderer {
+        Ok(renderer) => renderer,
+        Err(RendererError::NoAdapter) if std::env::var_os("RENDER_SKIP_GPU").is_some() => {
+            eprintln!(
+                "!!! SKIPPED: no GPU adapter and RENDER_SKIP_GPU is set, nothing was rendered"
+            );
+            return None;
+        }
+        Err(RendererError::NoAdapter) => panic!(
+            "there is no GPU adapter to render the golden images with, set RENDER_SKIP_GPU=1 to \
+             skip these tests on machines without one"
+        ),
+        Err(err) => panic!("failed to create the renderer: {err}"),
+    };
+
+    Some(renderer.capture().expect("frame can be captured"))
+}
+
+/// A dimmed grayscale copy of `expected` with differing pixels in red, and their count
+fn diff(expected: &RgbaImage, actual: &RgbaImage) -> (RgbaImage, usize) {
+    let mut differing = 0;
+
+    let diff = RgbaImage::from_fn(expected.width(), expected.height(), |x, y| {
+        let a = expected.get_pixel(x, y);
+        let b = actual.get_pixel(x, y);
+
+        let differs =
+            a.0.iter()
+                .zip(b.0)
+                .any(|(a, b)| a.abs_diff(b) > CHANNEL_TOLERANCE);
+
+        if differs {
+            differing += 1;
+            Rgba([255, 0, 0, 255])
+        } else {
+            let luma = (a[0] as u32 * 2 + a[1] as u32 * 5 + a[2] as u32) / 8;
+            let dimmed = (luma / 3) as u8;
+            Rgba([dimmed, dimmed, dimmed, 255])
+        }
+    });
+
+    (diff, differing)
+}
+
+fn side_by_side(left: &RgbaImage, right: &RgbaImage) -> RgbaImage {
+    let mut image = RgbaImage::new(left.width() + right.width(), left.height());
+    image::imageops::replace(&mut image, left, 0, 0);
+    image::imageops::replace(&mut image, right, left.width() as i64, 0);
+    image
+}
+
+fn manifest_path(path: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join(path)
+}